@@ -0,0 +1,219 @@
+use crate::Document;
+use crate::Position;
+use crate::Row;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Rectangular block selection: rows from `top` to `bottom` inclusive, screen columns from `left`
+/// inclusive to `right` exclusive
+#[derive(Clone, Copy)]
+pub struct Block {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Block {
+    /// Block with corners at `anchor` and `cursor` of `document`
+    pub fn new(
+        document: &Document,
+        anchor: &Position,
+        cursor: &Position,
+        tab_width: usize,
+    ) -> Self {
+        let anchor_column = document.render_x(anchor, tab_width);
+        let cursor_column = document.render_x(cursor, tab_width);
+        Self {
+            top: anchor.y.min(cursor.y),
+            bottom: anchor.y.max(cursor.y),
+            left: anchor_column.min(cursor_column),
+            right: anchor_column.max(cursor_column),
+        }
+    }
+
+    /// Number of rows
+    pub fn height(&self) -> usize {
+        self.bottom.saturating_sub(self.top).saturating_add(1)
+    }
+
+    /// Number of screen columns
+    pub fn width(&self) -> usize {
+        self.right.saturating_sub(self.left)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left == self.right
+    }
+
+    /// Text of the block in `document`, one line per row
+    pub fn text(&self, document: &Document, tab_width: usize) -> Vec<String> {
+        document.block_text(self.top, self.bottom, self.left, self.right, tab_width)
+    }
+
+    /// Delete text of the block from `document` and return position of its top left corner
+    pub fn cut(&self, document: &mut Document, tab_width: usize) -> Position {
+        let lines = vec![String::new(); self.height()];
+        document.replace_block(self.top, self.left, self.right, &lines, tab_width);
+        document.position_at(self.top, self.left, tab_width)
+    }
+
+    /// Replace text of every row of the block by `text` repeated to the width of block
+    pub fn fill(&self, document: &mut Document, text: &str, tab_width: usize) {
+        let lines = vec![fill_line(text, self.width()); self.height()];
+        document.replace_block(self.top, self.left, self.right, &lines, tab_width);
+    }
+}
+
+/// Insert `lines` of a copied block at the screen column of `at` on its row and rows below it
+pub fn paste(document: &mut Document, at: &Position, lines: &[String], tab_width: usize) {
+    let column = document.render_x(at, tab_width);
+    document.replace_block(at.y, column, column, lines, tab_width);
+}
+
+/// Insert `text` on every row of block with corners `anchor` and `cursor` before its left column,
+/// the corners move after the text
+pub fn insert(
+    document: &mut Document,
+    anchor: &mut Position,
+    cursor: &mut Position,
+    text: &str,
+    tab_width: usize,
+) {
+    let block = Block::new(document, anchor, cursor, tab_width);
+    let lines = vec![text.to_owned(); block.height()];
+    // Width of tabs depends on the column where the text starts
+    let width = Row::from(format!("{}{}", " ".repeat(block.left), text).as_str())
+        .width_until(usize::MAX, tab_width)
+        .saturating_sub(block.left);
+    let shift = |column: usize| column.saturating_add(width);
+    shift_corners(document, anchor, cursor, tab_width, shift, |document| {
+        document.replace_block(block.top, block.left, block.left, &lines, tab_width);
+    });
+}
+
+/// Delete text of block with corners `anchor` and `cursor`, which then is an empty block on the same rows.
+/// Empty block deletes the column before it with `backward`, the one after it otherwise.
+pub fn delete(
+    document: &mut Document,
+    anchor: &mut Position,
+    cursor: &mut Position,
+    backward: bool,
+    tab_width: usize,
+) {
+    let block = Block::new(document, anchor, cursor, tab_width);
+    let lines = vec![String::new(); block.height()];
+    if !block.is_empty() {
+        document.replace_block(block.top, block.left, block.right, &lines, tab_width);
+        *anchor = document.position_at(block.top, block.left, tab_width);
+        *cursor = document.position_at(block.bottom, block.left, tab_width);
+    } else if backward && block.left > 0 {
+        let left = block.left.saturating_sub(1);
+        let shift = |column: usize| column.saturating_sub(1);
+        shift_corners(document, anchor, cursor, tab_width, shift, |document| {
+            document.replace_block(block.top, left, block.left, &lines, tab_width);
+        });
+    } else if !backward {
+        let right = block.left.saturating_add(1);
+        document.replace_block(block.top, block.left, right, &lines, tab_width);
+    }
+}
+
+/// Change `document` by `edit` and move screen columns of corners `anchor` and `cursor` by `shift`
+fn shift_corners<S, E>(
+    document: &mut Document,
+    anchor: &mut Position,
+    cursor: &mut Position,
+    tab_width: usize,
+    shift: S,
+    edit: E,
+) where
+    S: Fn(usize) -> usize,
+    E: FnOnce(&mut Document),
+{
+    let anchor_column = shift(document.render_x(anchor, tab_width));
+    let cursor_column = shift(document.render_x(cursor, tab_width));
+    edit(document);
+    *anchor = document.position_at(anchor.y, anchor_column, tab_width);
+    *cursor = document.position_at(cursor.y, cursor_column, tab_width);
+}
+
+/// `text` repeated to fill `width` screen columns, the rest is padded with spaces
+fn fill_line(text: &str, width: usize) -> String {
+    let mut line = String::new();
+    let mut line_width: usize = 0;
+    for grapheme in text.graphemes(true).cycle() {
+        let grapheme_width = grapheme.width().max(1);
+        if line_width.saturating_add(grapheme_width) > width {
+            break;
+        }
+        line.push_str(grapheme);
+        line_width = line_width.saturating_add(grapheme_width);
+    }
+    line.push_str(&" ".repeat(width.saturating_sub(line_width)));
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    fn document(text: &str) -> Document {
+        let lines: Vec<String> = text.split(' ').map(str::to_owned).collect();
+        Document::view(text, &lines)
+    }
+
+    #[test]
+    fn spans_rows_and_columns_between_corners() {
+        let document = document("a\tb abcd");
+        let block = Block::new(&document, &at(3, 1), &at(1, 0), 4);
+        assert!((block.top, block.bottom, block.left, block.right) == (0, 1, 1, 3));
+        assert_eq!(block.height(), 2);
+        assert_eq!(block.width(), 2);
+        let block = Block::new(&document, &at(2, 0), &at(4, 1), 4);
+        assert!((block.left, block.right) == (4, 4));
+        assert!(block.is_empty());
+    }
+
+    #[test]
+    fn inserts_before_block_and_moves_it() {
+        let mut document = document("abc abc");
+        let (mut anchor, mut cursor) = (at(1, 0), at(2, 1));
+        insert(&mut document, &mut anchor, &mut cursor, "xy", 4);
+        assert_eq!(document.lines(), ["axybc", "axybc"]);
+        assert!(anchor == at(3, 0) && cursor == at(4, 1));
+    }
+
+    #[test]
+    fn deletes_block_or_column_next_to_empty_one() {
+        let mut document = document("abcd abcd");
+        let (mut anchor, mut cursor) = (at(1, 0), at(3, 1));
+        delete(&mut document, &mut anchor, &mut cursor, true, 4);
+        assert_eq!(document.lines(), ["ad", "ad"]);
+        assert!(anchor == at(1, 0) && cursor == at(1, 1));
+        delete(&mut document, &mut anchor, &mut cursor, true, 4);
+        assert_eq!(document.lines(), ["d", "d"]);
+        assert!(anchor == at(0, 0) && cursor == at(0, 1));
+        delete(&mut document, &mut anchor, &mut cursor, false, 4);
+        assert_eq!(document.lines(), ["", ""]);
+        delete(&mut document, &mut anchor, &mut cursor, true, 4);
+        assert_eq!(document.lines(), ["", ""]);
+    }
+
+    #[test]
+    fn fills_block_with_repeated_text() {
+        assert_eq!(fill_line("ab", 5), "ababa");
+        assert_eq!(fill_line("界", 3), "界 ");
+        let mut document = document("abcd abcd");
+        let block = Block::new(&document, &at(1, 0), &at(3, 1), 4);
+        block.fill(&mut document, "-", 4);
+        assert_eq!(document.lines(), ["a--d", "a--d"]);
+        assert!(block.cut(&mut document, 4) == at(1, 0));
+        assert_eq!(document.lines(), ["ad", "ad"]);
+    }
+}
@@ -0,0 +1,208 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of rows shown above the first entry of directory listing
+pub const HEADER_ROWS: usize = 1;
+
+/// File or directory shown in directory listing
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Content of a directory shown by the file browser. Directories are listed first, `..` is the first entry.
+pub struct Listing {
+    directory: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl Listing {
+    /// # Errors
+    ///
+    /// Will return `Err` if `directory` cannot be read
+    pub fn read(directory: &Path) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            // Metadata of symbolic links are followed, broken links are shown as files
+            let metadata = fs::metadata(entry.path()).or_else(|_| entry.metadata())?;
+            entries.push(Entry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: metadata.is_dir(),
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+        entries.insert(
+            0,
+            Entry {
+                name: "..".to_owned(),
+                is_dir: true,
+                len: 0,
+                modified: None,
+            },
+        );
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Entry shown at row `y`
+    pub fn entry(&self, y: usize) -> Option<&Entry> {
+        self.entries.get(y.checked_sub(HEADER_ROWS)?)
+    }
+
+    /// Row where entry `name` is shown
+    pub fn row_of(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.name == name)
+            .map(|index| index.saturating_add(HEADER_ROWS))
+    }
+
+    /// Path of `name` in the listed directory. `..` is resolved to the parent directory.
+    pub fn path(&self, name: &str) -> PathBuf {
+        if name == ".." {
+            return match self.directory.file_name() {
+                Some(_) => self
+                    .directory
+                    .parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                    .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+                None => fs::canonicalize(&self.directory)
+                    .ok()
+                    .and_then(|directory| directory.parent().map(Path::to_path_buf))
+                    .unwrap_or_else(|| self.directory.join("..")),
+            };
+        }
+        let path = self.directory.join(name);
+        path.strip_prefix(".")
+            .map_or_else(|_| path.clone(), Path::to_path_buf)
+    }
+
+    /// Rows of directory listing, the header with absolute path of directory followed by entries
+    pub fn lines(&self) -> Vec<String> {
+        let directory =
+            fs::canonicalize(&self.directory).unwrap_or_else(|_| self.directory.clone());
+        let mut lines = vec![format!("{}:", directory.display())];
+        for entry in &self.entries {
+            let size = if entry.is_dir {
+                "-".to_owned()
+            } else {
+                format_size(entry.len)
+            };
+            let modified = entry.modified.map_or_else(String::new, format_time);
+            let slash = if entry.is_dir { "/" } else { "" };
+            lines.push(format!(
+                "{:>8}  {:16}  {}{}",
+                size, modified, entry.name, slash
+            ));
+        }
+        lines
+    }
+}
+
+/// Size in bytes, or with binary unit prefix, e.g. `4.2K`
+#[allow(clippy::cast_precision_loss, clippy::float_arithmetic)]
+fn format_size(len: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if len < 1024 {
+        return len.to_string();
+    }
+    let mut size = len as f64 / 1024.0_f64;
+    let mut unit: usize = 0;
+    while size >= 1024.0_f64 && unit.saturating_add(1) < UNITS.len() {
+        size /= 1024.0_f64;
+        unit = unit.saturating_add(1);
+    }
+    format!("{:.1}{}", size, UNITS.get(unit).unwrap_or(&""))
+}
+
+/// Local time as `YYYY-MM-DD HH:MM`
+pub fn format_time(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let seconds = libc::time_t::try_from(seconds).unwrap_or(libc::time_t::MAX);
+    // SAFETY: `tm` is plain data which may be zeroed, both pointers are valid during the call
+    #[allow(unsafe_code)]
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&seconds, &mut tm).is_null() {
+            return String::new();
+        }
+        tm
+    };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        tm.tm_year.saturating_add(1900),
+        tm.tm_mon.saturating_add(1),
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn lists_directories_first() {
+        let directory = env::temp_dir().join(format!("hecto-browse-{}", process::id()));
+        fs::create_dir_all(directory.join("sub")).unwrap();
+        fs::write(directory.join("a.txt"), "hello").unwrap();
+        let listing = Listing::read(&directory);
+        fs::remove_dir_all(&directory).unwrap();
+        let listing = listing.unwrap();
+        let names: Vec<&str> = (HEADER_ROWS..HEADER_ROWS + 3)
+            .filter_map(|y| listing.entry(y).map(|entry| entry.name.as_str()))
+            .collect();
+        assert_eq!(names, ["..", "sub", "a.txt"]);
+        assert!(listing.entry(0).is_none());
+        assert_eq!(listing.row_of("a.txt"), Some(HEADER_ROWS + 2));
+        let lines = listing.lines();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].trim_start().starts_with("-  ") && lines[2].ends_with(" sub/"));
+        assert!(lines[3].trim_start().starts_with("5  ") && lines[3].ends_with(" a.txt"));
+    }
+
+    #[test]
+    fn resolves_paths() {
+        let listing = Listing {
+            directory: PathBuf::from("src/bin"),
+            entries: Vec::new(),
+        };
+        assert_eq!(listing.path("main.rs"), PathBuf::from("src/bin/main.rs"));
+        assert_eq!(listing.path(".."), PathBuf::from("src"));
+        let listing = Listing {
+            directory: PathBuf::from("src"),
+            entries: Vec::new(),
+        };
+        assert_eq!(listing.path(".."), PathBuf::from("."));
+        let listing = Listing {
+            directory: PathBuf::from("."),
+            entries: Vec::new(),
+        };
+        assert_eq!(listing.path("Cargo.toml"), PathBuf::from("Cargo.toml"));
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(0), "0");
+        assert_eq!(format_size(1023), "1023");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0M");
+    }
+}
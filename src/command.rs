@@ -0,0 +1,290 @@
+use std::cmp;
+
+use crate::fuzzy;
+
+/// Named command of the command line with a hint of its arguments
+pub struct Command {
+    pub name: &'static str,
+    pub arguments: &'static str,
+    pub description: &'static str,
+}
+
+pub const COMMANDS: [Command; 42] = [
+    Command {
+        name: "goto",
+        arguments: "<line>[:<column>] | +<n> | -<n> | <n>%",
+        description: "go to line",
+    },
+    Command {
+        name: "set",
+        arguments: "tabwidth=<n> | indent=tabs | indent=<n>",
+        description: "change option",
+    },
+    Command {
+        name: "save",
+        arguments: "",
+        description: "save file",
+    },
+    Command {
+        name: "saveas",
+        arguments: "[<file>]",
+        description: "save file under new name",
+    },
+    Command {
+        name: "open",
+        arguments: "[<file>]",
+        description: "open file",
+    },
+    Command {
+        name: "new",
+        arguments: "",
+        description: "start new empty buffer",
+    },
+    Command {
+        name: "files",
+        arguments: "",
+        description: "find file in project",
+    },
+    Command {
+        name: "grep",
+        arguments: "[<text>]",
+        description: "search text in all files",
+    },
+    Command {
+        name: "browse",
+        arguments: "[<directory>]",
+        description: "browse files in directory",
+    },
+    Command {
+        name: "reload",
+        arguments: "",
+        description: "reload file from disk",
+    },
+    Command {
+        name: "sort",
+        arguments: "",
+        description: "sort selected lines or whole file",
+    },
+    Command {
+        name: "diff",
+        arguments: "",
+        description: "show changes against saved file",
+    },
+    Command {
+        name: "next-hunk",
+        arguments: "",
+        description: "go to next change against git HEAD",
+    },
+    Command {
+        name: "prev-hunk",
+        arguments: "",
+        description: "go to previous change against git HEAD",
+    },
+    Command {
+        name: "revert-hunk",
+        arguments: "",
+        description: "revert change under cursor to git HEAD",
+    },
+    Command {
+        name: "show-hunk",
+        arguments: "",
+        description: "show git HEAD version of change under cursor",
+    },
+    Command {
+        name: "record",
+        arguments: "[<name>]",
+        description: "start or stop recording keyboard macro",
+    },
+    Command {
+        name: "play",
+        arguments: "<name> [<count> | all]",
+        description: "replay keyboard macro",
+    },
+    Command {
+        name: "bind",
+        arguments: "<key> [<name>]",
+        description: "bind key (e.g. M-1, F5) to macro",
+    },
+    Command {
+        name: "save-macros",
+        arguments: "",
+        description: "save macros and bindings to disk",
+    },
+    Command {
+        name: "cursor-next-match",
+        arguments: "",
+        description: "add cursor at next match of selection or word",
+    },
+    Command {
+        name: "cursor-all-matches",
+        arguments: "",
+        description: "add cursors at all matches of selection or word",
+    },
+    Command {
+        name: "cursor-column",
+        arguments: "",
+        description: "add cursor on every selected line",
+    },
+    Command {
+        name: "block",
+        arguments: "",
+        description: "start or cancel block selection",
+    },
+    Command {
+        name: "block-copy",
+        arguments: "",
+        description: "copy block selection",
+    },
+    Command {
+        name: "block-cut",
+        arguments: "",
+        description: "cut block selection",
+    },
+    Command {
+        name: "block-paste",
+        arguments: "",
+        description: "paste copied block at cursor",
+    },
+    Command {
+        name: "block-insert",
+        arguments: "<text>",
+        description: "insert text on every line of block",
+    },
+    Command {
+        name: "block-fill",
+        arguments: "<text>",
+        description: "fill block with repeated text",
+    },
+    Command {
+        name: "next-conflict",
+        arguments: "",
+        description: "go to next merge conflict",
+    },
+    Command {
+        name: "prev-conflict",
+        arguments: "",
+        description: "go to previous merge conflict",
+    },
+    Command {
+        name: "resolve",
+        arguments: "ours | theirs | both",
+        description: "resolve merge conflict under cursor",
+    },
+    Command {
+        name: "put-hunk",
+        arguments: "",
+        description: "copy change under cursor to the other compared file",
+    },
+    Command {
+        name: "get-hunk",
+        arguments: "",
+        description: "take change under cursor from the other compared file",
+    },
+    Command {
+        name: "close-compare",
+        arguments: "",
+        description: "stop comparing files side by side",
+    },
+    Command {
+        name: "blame",
+        arguments: "",
+        description: "show or hide commits which changed lines",
+    },
+    Command {
+        name: "blame-commit",
+        arguments: "",
+        description: "show commit which changed line under cursor",
+    },
+    Command {
+        name: "write-region",
+        arguments: "<file>",
+        description: "write selection to file",
+    },
+    Command {
+        name: "indent-tabs",
+        arguments: "",
+        description: "convert indentation to tabs",
+    },
+    Command {
+        name: "indent-spaces",
+        arguments: "[<width>]",
+        description: "convert indentation to spaces",
+    },
+    Command {
+        name: "filetype",
+        arguments: "<rust | diff | none>",
+        description: "set file type",
+    },
+    Command {
+        name: "quit",
+        arguments: "",
+        description: "quit editor",
+    },
+];
+
+/// Commands whose name fuzzy matches `query`, the best match first
+pub fn matching(query: &str) -> Vec<&'static Command> {
+    let mut matches: Vec<(i64, &Command)> = COMMANDS
+        .iter()
+        .filter_map(|command| fuzzy::score(query, command.name).map(|score| (score, command)))
+        .collect();
+    matches.sort_by_key(|&(score, _)| cmp::Reverse(score));
+    matches.into_iter().map(|(_, command)| command).collect()
+}
+
+/// Command with exactly `name`, or the only command whose name starts with `name`
+pub fn find(name: &str) -> Option<&'static Command> {
+    if let Some(command) = COMMANDS.iter().find(|command| command.name == name) {
+        return Some(command);
+    }
+    let mut prefixed = COMMANDS
+        .iter()
+        .filter(|command| !name.is_empty() && command.name.starts_with(name));
+    match (prefixed.next(), prefixed.next()) {
+        (Some(command), None) => Some(command),
+        _ => None,
+    }
+}
+
+/// Split command line into command name and its arguments
+pub fn split(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    line.split_once(char::is_whitespace)
+        .map_or((line, ""), |(name, arguments)| (name, arguments.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(name: &str) -> Option<&'static str> {
+        find(name).map(|command| command.name)
+    }
+
+    #[test]
+    fn finds_exact_or_unique_prefix() {
+        assert_eq!(found("save"), Some("save"));
+        assert_eq!(found("savea"), Some("saveas"));
+        assert_eq!(found("q"), Some("quit"));
+        assert_eq!(found("s"), None);
+        assert_eq!(found("sv"), None);
+        assert_eq!(found(""), None);
+    }
+
+    #[test]
+    fn orders_matching_commands_by_score() {
+        let names: Vec<&str> = matching("sa").iter().map(|command| command.name).collect();
+        assert_eq!(names.first(), Some(&"save"));
+        assert!(names.contains(&"saveas"));
+        assert!(names.contains(&"save-macros"));
+        assert!(!names.contains(&"goto"));
+        assert_eq!(matching("").len(), COMMANDS.len());
+    }
+
+    #[test]
+    fn splits_command_line() {
+        assert_eq!(split("  goto  12:3 "), ("goto", "12:3"));
+        assert_eq!(split("quit"), ("quit", ""));
+        assert_eq!(split("set tabwidth=2"), ("set", "tabwidth=2"));
+    }
+}
@@ -0,0 +1,542 @@
+use crate::diff::{self, Change, Hunk};
+use crate::Document;
+use crate::Position;
+use crate::Row;
+use crate::SearchOptions;
+
+use std::mem;
+use std::ops::Range;
+use termion::color;
+use unicode_segmentation::UnicodeSegmentation;
+
+const CHANGED_LINE_BG_COLOR: color::Rgb = color::Rgb(50, 60, 90);
+const CHANGED_WORD_BG_COLOR: color::Rgb = color::Rgb(90, 105, 160);
+const FILLER_BG_COLOR: color::Rgb = color::Rgb(45, 45, 45);
+
+/// Row of side-by-side diff, lines of left and right file shown next to each other.
+/// Line missing on one side is shown as a filler.
+pub struct AlignedRow {
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub changed: bool,
+}
+
+/// Alignment of lines of two files compared side by side
+#[derive(Default)]
+pub struct Comparison {
+    rows: Vec<AlignedRow>,
+    hunks: Vec<Hunk>,
+    /// Aligned row of each line of left and right file
+    left_rows: Vec<usize>,
+    right_rows: Vec<usize>,
+    /// Revisions of left and right document the alignment was computed for
+    revisions: Option<(usize, usize)>,
+}
+
+impl Comparison {
+    /// Align lines of `left` and `right` again if any of them changed
+    pub fn update(&mut self, left: &Document, right: &Document) {
+        let revisions = Some((left.revision(), right.revision()));
+        if self.revisions == revisions {
+            return;
+        }
+        self.revisions = revisions;
+        let changes = diff::diff(&left.lines(), &right.lines());
+        self.hunks = diff::hunks(&changes, 0);
+        self.rows = Vec::new();
+        let mut deleted = Vec::new();
+        let mut inserted = Vec::new();
+        for change in changes {
+            match change {
+                Change::Delete(index) => deleted.push(index),
+                Change::Insert(index) => inserted.push(index),
+                Change::Equal(old, new) => {
+                    self.push_changed(&mut deleted, &mut inserted);
+                    self.rows.push(AlignedRow {
+                        left: Some(old),
+                        right: Some(new),
+                        changed: false,
+                    });
+                }
+            }
+        }
+        self.push_changed(&mut deleted, &mut inserted);
+        self.left_rows = vec![0; left.len()];
+        self.right_rows = vec![0; right.len()];
+        for (index, row) in self.rows.iter().enumerate() {
+            if let Some(slot) = row.left.and_then(|line| self.left_rows.get_mut(line)) {
+                *slot = index;
+            }
+            if let Some(slot) = row.right.and_then(|line| self.right_rows.get_mut(line)) {
+                *slot = index;
+            }
+        }
+    }
+
+    /// Pair removed lines with added lines which replace them
+    fn push_changed(&mut self, deleted: &mut Vec<usize>, inserted: &mut Vec<usize>) {
+        let len = deleted.len().max(inserted.len());
+        for index in 0..len {
+            self.rows.push(AlignedRow {
+                left: deleted.get(index).copied(),
+                right: inserted.get(index).copied(),
+                changed: true,
+            });
+        }
+        deleted.clear();
+        inserted.clear();
+    }
+
+    pub fn rows(&self) -> &[AlignedRow] {
+        &self.rows
+    }
+
+    /// Aligned row where `line` of left or right file is shown
+    pub fn row_of(&self, line: usize, left: bool) -> usize {
+        let rows = if left {
+            &self.left_rows
+        } else {
+            &self.right_rows
+        };
+        rows.get(line).copied().unwrap_or_else(|| {
+            self.rows
+                .len()
+                .saturating_add(line.saturating_sub(rows.len()))
+        })
+    }
+
+    /// Line of left or right file shown at aligned row `row` or the nearest one after it
+    pub fn line_at(&self, row: usize, left: bool) -> usize {
+        let rows = if left {
+            &self.left_rows
+        } else {
+            &self.right_rows
+        };
+        rows.iter()
+            .position(|&line_row| line_row >= row)
+            .unwrap_or_else(|| rows.len().saturating_sub(1))
+    }
+
+    /// First line of `hunk` on the left or right side, lines removed on that side are shown before the line
+    fn hunk_line(hunk: &Hunk, left: bool) -> usize {
+        if left {
+            hunk.old_start
+        } else {
+            hunk.new_start
+        }
+    }
+
+    fn hunk_len(hunk: &Hunk, left: bool) -> usize {
+        if left {
+            hunk.old_len
+        } else {
+            hunk.new_len
+        }
+    }
+
+    /// Hunk which contains `line` of left or right file
+    pub fn hunk_at(&self, line: usize, left: bool) -> Option<&Hunk> {
+        self.hunks.iter().find(|hunk| {
+            let start = Self::hunk_line(hunk, left);
+            line >= start && line < start.saturating_add(Self::hunk_len(hunk, left).max(1))
+        })
+    }
+
+    /// First line of the nearest hunk after `line` of left or right file
+    pub fn next_hunk(&self, line: usize, left: bool) -> Option<usize> {
+        self.hunks
+            .iter()
+            .map(|hunk| Self::hunk_line(hunk, left))
+            .find(|&start| start > line)
+    }
+
+    /// First line of the nearest hunk before `line` of left or right file
+    pub fn previous_hunk(&self, line: usize, left: bool) -> Option<usize> {
+        let current = self
+            .hunk_at(line, left)
+            .map(|hunk| Self::hunk_line(hunk, left));
+        self.hunks
+            .iter()
+            .map(|hunk| Self::hunk_line(hunk, left))
+            .rfind(|&start| start < current.unwrap_or(line))
+    }
+}
+
+/// File compared side by side with the current document. The other file has its own cursor and
+/// scrolling, which are swapped with the current ones when the other side is edited.
+pub struct Compare {
+    other: Document,
+    other_cursor: Position,
+    other_offset: Position,
+    /// The current document is shown on the left side
+    active_left: bool,
+    comparison: Comparison,
+}
+
+impl Compare {
+    /// Comparison of the current document with `other`, which is shown on the right
+    pub fn new(other: Document) -> Self {
+        Self {
+            other,
+            other_cursor: Position::default(),
+            other_offset: Position::default(),
+            active_left: true,
+            comparison: Comparison::default(),
+        }
+    }
+
+    pub fn other(&self) -> &Document {
+        &self.other
+    }
+
+    pub fn is_active_left(&self) -> bool {
+        self.active_left
+    }
+
+    /// Align lines of the current `document` and the other one again if any of them changed
+    pub fn update(&mut self, document: &Document) {
+        if self.active_left {
+            self.comparison.update(document, &self.other);
+        } else {
+            self.comparison.update(&self.other, document);
+        }
+    }
+
+    /// Aligned row where line `y` of the current document is shown
+    pub fn row_of(&self, y: usize) -> usize {
+        self.comparison.row_of(y, self.active_left)
+    }
+
+    /// Line of the current document shown at aligned row `row` or the nearest one after it
+    pub fn line_at(&self, row: usize) -> usize {
+        self.comparison.line_at(row, self.active_left)
+    }
+
+    /// Highlight the other document as far as it's shown on `height` rows from line `top` of the current one
+    pub fn highlight_other(&mut self, top: usize, height: usize, search_options: SearchOptions) {
+        let last_row = self.row_of(top).saturating_add(height);
+        let until = self.comparison.line_at(last_row, !self.active_left);
+        self.other.highlight(&None, search_options, Some(until));
+    }
+
+    /// Make the other document the current one. It's swapped with `document`, `cursor` and `offset`,
+    /// the cursor moves to the line aligned with the current one.
+    pub fn switch_side(
+        &mut self,
+        document: &mut Document,
+        cursor: &mut Position,
+        offset: &mut Position,
+    ) {
+        let cursor_row = self.row_of(cursor.y);
+        let top_row = self.row_of(offset.y);
+        mem::swap(document, &mut self.other);
+        mem::swap(cursor, &mut self.other_cursor);
+        mem::swap(offset, &mut self.other_offset);
+        self.active_left = !self.active_left;
+        let y = self.line_at(cursor_row);
+        *cursor = Position {
+            x: cursor.x.min(document.row_len(y)),
+            y,
+        };
+        offset.y = self.line_at(top_row);
+    }
+
+    /// First line of the nearest hunk after line `y` of the current document
+    pub fn next_hunk(&self, y: usize) -> Option<usize> {
+        self.comparison.next_hunk(y, self.active_left)
+    }
+
+    /// First line of the nearest hunk before line `y` of the current document
+    pub fn previous_hunk(&self, y: usize) -> Option<usize> {
+        self.comparison.previous_hunk(y, self.active_left)
+    }
+
+    /// Lines of the current and the other document changed by hunk at line `y` of the current one
+    fn hunk_lines(&self, y: usize) -> Option<(Range<usize>, Range<usize>)> {
+        let hunk = self.comparison.hunk_at(y, self.active_left)?;
+        let old = hunk.old_start..hunk.old_start.saturating_add(hunk.old_len);
+        let new = hunk.new_start..hunk.new_start.saturating_add(hunk.new_len);
+        Some(if self.active_left {
+            (old, new)
+        } else {
+            (new, old)
+        })
+    }
+
+    /// Copy hunk at line `y` of the current `document` to the other one. Returns `false` if there is no hunk.
+    pub fn put_hunk(&mut self, document: &Document, y: usize) -> bool {
+        if let Some((active, other)) = self.hunk_lines(y) {
+            let lines = document.lines().get(active).unwrap_or_default().to_vec();
+            self.other.replace_rows(other.start, other.len(), &lines);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Replace hunk at line `y` of the current `document` by the other document's version. Returns the
+    /// first line of the hunk.
+    pub fn get_hunk(&self, document: &mut Document, y: usize) -> Option<usize> {
+        let (active, other) = self.hunk_lines(y)?;
+        let lines = self.other.lines().get(other).unwrap_or_default().to_vec();
+        document.replace_rows(active.start, active.len(), &lines);
+        Some(active.start)
+    }
+
+    /// Left and right side of aligned row `index`, `None` after the last one. The current `document`
+    /// is scrolled horizontally by `offset_x`, sides are `widths` columns wide.
+    pub fn render_row(
+        &self,
+        document: &Document,
+        offset_x: usize,
+        index: usize,
+        widths: (usize, usize),
+        tab_width: usize,
+    ) -> Option<(String, String)> {
+        let aligned = self.comparison.rows().get(index)?;
+        let (left, right) = if self.active_left {
+            ((document, offset_x), (&self.other, self.other_offset.x))
+        } else {
+            ((&self.other, self.other_offset.x), (document, offset_x))
+        };
+        let left_row = aligned.left.and_then(|y| left.0.row(y));
+        let right_row = aligned.right.and_then(|y| right.0.row(y));
+        let (left_words, right_words) = match (left_row, right_row) {
+            (Some(left_row), Some(right_row)) if aligned.changed => {
+                changed_words(&left_row.get_string(), &right_row.get_string())
+            }
+            _ => (Vec::new(), Vec::new()),
+        };
+        Some((
+            render_cell(
+                left_row,
+                aligned.changed,
+                &left_words,
+                left.1,
+                widths.0,
+                tab_width,
+            ),
+            render_cell(
+                right_row,
+                aligned.changed,
+                &right_words,
+                right.1,
+                widths.1,
+                tab_width,
+            ),
+        ))
+    }
+}
+
+/// One side of aligned row, `None` is a filler. Changed lines have background, changed words inside
+/// them a brighter one.
+fn render_cell(
+    row: Option<&Row>,
+    changed: bool,
+    words: &[(usize, usize)],
+    offset_x: usize,
+    width: usize,
+    tab_width: usize,
+) -> String {
+    let row = if let Some(row) = row {
+        row
+    } else {
+        return format!(
+            "{}{}{}",
+            color::Bg(FILLER_BG_COLOR),
+            " ".repeat(width),
+            color::Bg(color::Reset)
+        );
+    };
+    let line_bg = if changed {
+        Some(CHANGED_LINE_BG_COLOR)
+    } else {
+        None
+    };
+    let text = row.render_with_background(
+        offset_x,
+        offset_x.saturating_add(width),
+        tab_width,
+        None,
+        |index| {
+            if words.iter().any(|&(from, to)| from <= index && index < to) {
+                Some(CHANGED_WORD_BG_COLOR)
+            } else {
+                line_bg
+            }
+        },
+    );
+    let visible = row
+        .width_until(row.len(), tab_width)
+        .saturating_sub(offset_x)
+        .min(width);
+    let fill = " ".repeat(width.saturating_sub(visible));
+    if let Some(line_bg) = line_bg {
+        format!(
+            "{}{}{}{}",
+            text,
+            color::Bg(line_bg),
+            fill,
+            color::Bg(color::Reset)
+        )
+    } else {
+        format!("{}{}", text, fill)
+    }
+}
+
+/// Start and end indexes of graphemes
+pub type Ranges = Vec<(usize, usize)>;
+
+/// Grapheme ranges of words which differ between `old` and `new` line
+pub fn changed_words(old: &str, new: &str) -> (Ranges, Ranges) {
+    let old_words = word_ranges(old);
+    let new_words = word_ranges(new);
+    let old_texts: Vec<&str> = old_words.iter().map(|(word, _)| *word).collect();
+    let new_texts: Vec<&str> = new_words.iter().map(|(word, _)| *word).collect();
+    let mut old_changed = Vec::new();
+    let mut new_changed = Vec::new();
+    for change in diff::diff(&old_texts, &new_texts) {
+        match change {
+            Change::Delete(index) => {
+                if let Some(&(_, range)) = old_words.get(index) {
+                    old_changed.push(range);
+                }
+            }
+            Change::Insert(index) => {
+                if let Some(&(_, range)) = new_words.get(index) {
+                    new_changed.push(range);
+                }
+            }
+            Change::Equal(..) => (),
+        }
+    }
+    (old_changed, new_changed)
+}
+
+/// Words and separators of `line` with their grapheme ranges
+fn word_ranges(line: &str) -> Vec<(&str, (usize, usize))> {
+    let mut start: usize = 0;
+    line.split_word_bounds()
+        .map(|word| {
+            let end = start.saturating_add(word.graphemes(true).count());
+            let range = (start, end);
+            start = end;
+            (word, range)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text: &str) -> Document {
+        let lines: Vec<String> = text.split(' ').map(str::to_owned).collect();
+        Document::view(text, &lines)
+    }
+
+    fn comparison(left: &str, right: &str) -> Comparison {
+        let mut comparison = Comparison::default();
+        comparison.update(&document(left), &document(right));
+        comparison
+    }
+
+    #[test]
+    fn aligns_changed_lines() {
+        let comparison = comparison("a b b2 c", "a x c d");
+        let rows: Vec<(Option<usize>, Option<usize>, bool)> = comparison
+            .rows()
+            .iter()
+            .map(|row| (row.left, row.right, row.changed))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (Some(0), Some(0), false),
+                (Some(1), Some(1), true),
+                (Some(2), None, true),
+                (Some(3), Some(2), false),
+                (None, Some(3), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn maps_lines_to_aligned_rows() {
+        let comparison = comparison("a b b2 c", "a x c d");
+        assert_eq!(comparison.row_of(3, true), 3);
+        assert_eq!(comparison.row_of(2, false), 3);
+        assert_eq!(comparison.row_of(3, false), 4);
+        // Lines past the end follow the last aligned row
+        assert_eq!(comparison.row_of(5, true), 6);
+        assert_eq!(comparison.line_at(2, true), 2);
+        // Filler row shows the next line of the other side
+        assert_eq!(comparison.line_at(2, false), 2);
+        assert_eq!(comparison.line_at(4, true), 3);
+    }
+
+    #[test]
+    fn moves_between_hunks() {
+        let comparison = comparison("a b b2 c", "a x c d");
+        assert_eq!(comparison.next_hunk(0, true), Some(1));
+        assert_eq!(comparison.next_hunk(1, false), Some(3));
+        assert_eq!(comparison.next_hunk(3, false), None);
+        assert_eq!(comparison.previous_hunk(3, false), Some(1));
+        assert_eq!(comparison.previous_hunk(2, true), None);
+        assert!(comparison.hunk_at(2, true).is_some());
+        assert!(comparison.hunk_at(2, false).is_none());
+    }
+
+    #[test]
+    fn keeps_alignment_of_same_revisions() {
+        let mut comparison = comparison("a", "b");
+        comparison.update(&document("a"), &document("a"));
+        assert!(comparison.rows().iter().all(|row| row.changed));
+    }
+
+    #[test]
+    fn switches_to_aligned_line_of_other_side() {
+        let mut current = document("a b b2 c");
+        let mut compare = Compare::new(document("a x c d"));
+        compare.update(&current);
+        let mut cursor = Position { x: 1, y: 3 };
+        let mut offset = Position { x: 0, y: 2 };
+        compare.switch_side(&mut current, &mut cursor, &mut offset);
+        assert!(!compare.is_active_left());
+        assert_eq!(current.lines(), ["a", "x", "c", "d"]);
+        assert_eq!(compare.other().lines(), ["a", "b", "b2", "c"]);
+        // The other side keeps its own column
+        assert!(cursor == Position { x: 0, y: 2 });
+        // Filler row of the removed line is above the next line of the other side
+        assert_eq!(offset.y, 2);
+    }
+
+    #[test]
+    fn copies_hunks_between_sides() {
+        let mut current = document("a b b2 c");
+        let mut compare = Compare::new(document("a x c d"));
+        compare.update(&current);
+        assert!(compare.put_hunk(&current, 1));
+        assert_eq!(compare.other().lines(), ["a", "b", "b2", "c", "d"]);
+        assert!(!compare.put_hunk(&current, 0));
+        compare.update(&current);
+        assert_eq!(compare.next_hunk(0), Some(4));
+
+        let mut compare = Compare::new(document("a x c d"));
+        compare.update(&current);
+        assert_eq!(compare.get_hunk(&mut current, 2), Some(1));
+        assert_eq!(current.lines(), ["a", "x", "c"]);
+    }
+
+    #[test]
+    fn finds_changed_words() {
+        assert_eq!(
+            changed_words("let a = 1;", "let b = 1;"),
+            (vec![(4, 5)], vec![(4, 5)])
+        );
+        assert_eq!(
+            changed_words("a b", "a b c"),
+            (vec![], vec![(3, 4), (4, 5)])
+        );
+    }
+}
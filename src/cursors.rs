@@ -0,0 +1,358 @@
+use crate::Document;
+use crate::Position;
+use crate::SearchOptions;
+
+use std::ops::RangeInclusive;
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Cursors besides the main one, ordered and without duplicates. Edits and movements apply to all of them.
+#[derive(Default)]
+pub struct Cursors {
+    extra: Vec<Position>,
+}
+
+impl Cursors {
+    pub fn is_empty(&self) -> bool {
+        self.extra.is_empty()
+    }
+
+    /// Number of cursors including the main one
+    pub fn count(&self) -> usize {
+        self.extra.len().saturating_add(1)
+    }
+
+    pub fn clear(&mut self) {
+        self.extra.clear();
+    }
+
+    /// Columns of cursors on row `y`
+    pub fn columns_in_row(&self, y: usize) -> Vec<usize> {
+        self.extra
+            .iter()
+            .filter(|cursor| cursor.y == y)
+            .map(|cursor| cursor.x)
+            .collect()
+    }
+
+    /// All cursors with `main` one in order of their positions, and index of the main cursor among them
+    pub fn all(&self, main: &Position) -> (Vec<Position>, usize) {
+        let mut cursors = self.extra.clone();
+        let index = cursors
+            .iter()
+            .position(|cursor| (cursor.y, cursor.x) > (main.y, main.x))
+            .unwrap_or(cursors.len());
+        cursors.insert(index, main.clone());
+        (cursors, index)
+    }
+
+    /// Make cursor at index `main` of `cursors` the main one, moved to `cursor`. Duplicates are removed.
+    pub fn set(&mut self, mut cursors: Vec<Position>, main: usize, cursor: &mut Position) {
+        if let Some(main_cursor) = cursors.get(main) {
+            *cursor = main_cursor.clone();
+        }
+        cursors.sort_by_key(|position| (position.y, position.x));
+        cursors.dedup();
+        cursors.retain(|other| other != cursor);
+        self.extra = cursors;
+    }
+
+    /// Add cursor at the first of match `ends` after the main `cursor`, or from the start of document, which
+    /// has no cursor yet. The new cursor becomes the main one. Returns `false` if all matches have a cursor.
+    pub fn add_next_match(&mut self, cursor: &mut Position, ends: Vec<Position>) -> bool {
+        let (mut cursors, _) = self.all(cursor);
+        let free: Vec<Position> = ends
+            .into_iter()
+            .filter(|end| !cursors.contains(end))
+            .collect();
+        let last = (cursor.y, cursor.x);
+        let next = free
+            .iter()
+            .find(|end| (end.y, end.x) > last)
+            .or_else(|| free.first())
+            .cloned();
+        if let Some(next) = next {
+            cursors.push(next);
+            let main = cursors.len().saturating_sub(1);
+            self.set(cursors, main, cursor);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Add cursors at all match `ends`. The main `cursor` stays if it's at one of them, otherwise it moves to the first.
+    /// Returns `false` if there are no matches.
+    pub fn add_all_matches(&mut self, cursor: &mut Position, mut ends: Vec<Position>) -> bool {
+        if ends.is_empty() {
+            return false;
+        }
+        let main = ends.iter().position(|end| end == cursor).unwrap_or(0);
+        ends.append(&mut self.extra);
+        self.set(ends, main, cursor);
+        true
+    }
+
+    /// Add cursor on the row below the lowest cursor, or above the highest one, in the column of the main `cursor`.
+    /// The new cursor becomes the main one.
+    pub fn add_in_column(&mut self, cursor: &mut Position, document: &Document, down: bool) {
+        let (mut cursors, _) = self.all(cursor);
+        let y = if down {
+            cursors
+                .last()
+                .map(|last| last.y.saturating_add(1))
+                .filter(|&y| y < document.len())
+        } else {
+            cursors.first().and_then(|first| first.y.checked_sub(1))
+        };
+        if let Some(y) = y {
+            let x = cursor.x.min(document.row_len(y));
+            cursors.push(Position { x, y });
+            let main = cursors.len().saturating_sub(1);
+            self.set(cursors, main, cursor);
+        }
+    }
+
+    /// Replace all cursors by one on each of `rows` in the column of the main `cursor`, which stays on its row
+    pub fn set_in_rows(
+        &mut self,
+        cursor: &mut Position,
+        document: &Document,
+        rows: RangeInclusive<usize>,
+    ) {
+        let main = cursor.y.saturating_sub(*rows.start());
+        let cursors: Vec<Position> = rows
+            .map(|y| Position {
+                x: cursor.x.min(document.row_len(y)),
+                y,
+            })
+            .collect();
+        self.set(cursors, main, cursor);
+    }
+}
+
+/// Ends of all matches of `query` in `document`
+pub fn match_ends(document: &Document, query: &str, mut options: SearchOptions) -> Vec<Position> {
+    let len = query.graphemes(true).count();
+    options.wrap_around = false;
+    document
+        .find_all(query, options)
+        .into_iter()
+        .map(|position| Position {
+            x: position.x.saturating_add(len),
+            y: position.y,
+        })
+        .collect()
+}
+
+/// Apply typing or deleting `key` at all `cursors` of `document`, which move with the text around them.
+/// Typed characters are inserted as they are when `pasting`.
+pub fn edit(
+    document: &mut Document,
+    cursors: &mut [Position],
+    key: Key,
+    tab_width: usize,
+    pasting: bool,
+) {
+    for index in 0..cursors.len() {
+        let at = if let Some(at) = cursors.get(index) {
+            at.clone()
+        } else {
+            break;
+        };
+        // Cursors which met in one position edit only once
+        if index > 0 && cursors.get(index.saturating_sub(1)) == Some(&at) {
+            continue;
+        }
+        // Text between start and old end was replaced by text between start and new end
+        if let Some((start, old_end, new_end)) = edit_at(document, &at, key, tab_width, pasting) {
+            for cursor in cursors.iter_mut() {
+                *cursor = shift_position(cursor, &start, &old_end, &new_end);
+            }
+        }
+    }
+}
+
+/// Edit `document` at position `at` as `key` does. Returns start of changed text with its end before and after the edit.
+fn edit_at(
+    document: &mut Document,
+    at: &Position,
+    key: Key,
+    tab_width: usize,
+    pasting: bool,
+) -> Option<(Position, Position, Position)> {
+    match key {
+        Key::Char('\t') if !pasting => {
+            let new_end = document.insert_tab(at, tab_width);
+            Some((at.clone(), at.clone(), new_end))
+        }
+        Key::Char(c) => {
+            let new_end = if pasting {
+                document.insert_pasted(at, c)
+            } else {
+                document.insert(at, c)
+            };
+            Some((at.clone(), at.clone(), new_end))
+        }
+        Key::Delete => {
+            let old_end = if at.x < document.row_len(at.y) {
+                Position {
+                    x: at.x.saturating_add(1),
+                    y: at.y,
+                }
+            } else if at.y.saturating_add(1) < document.len() {
+                Position {
+                    x: 0,
+                    y: at.y.saturating_add(1),
+                }
+            } else {
+                return None;
+            };
+            document.delete(at);
+            Some((at.clone(), old_end, at.clone()))
+        }
+        Key::Backspace => {
+            let start = if at.x > 0 {
+                Position {
+                    x: at.x.saturating_sub(1),
+                    y: at.y,
+                }
+            } else if at.y > 0 {
+                let y = at.y.saturating_sub(1);
+                Position {
+                    x: document.row_len(y),
+                    y,
+                }
+            } else {
+                return None;
+            };
+            document.delete(&start);
+            Some((start.clone(), at.clone(), start))
+        }
+        _ => None,
+    }
+}
+
+/// New position of `position` after text between `start` and `old_end` was replaced by text ending at `new_end`
+fn shift_position(
+    position: &Position,
+    start: &Position,
+    old_end: &Position,
+    new_end: &Position,
+) -> Position {
+    let at = (position.y, position.x);
+    if at < (start.y, start.x) {
+        position.clone()
+    } else if at < (old_end.y, old_end.x) {
+        start.clone()
+    } else if position.y == old_end.y {
+        Position {
+            x: new_end
+                .x
+                .saturating_add(position.x.saturating_sub(old_end.x)),
+            y: new_end.y,
+        }
+    } else {
+        Position {
+            x: position.x,
+            y: position
+                .y
+                .saturating_add(new_end.y)
+                .saturating_sub(old_end.y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    fn document(text: &str) -> Document {
+        let lines: Vec<String> = text.split(' ').map(str::to_owned).collect();
+        Document::view(text, &lines)
+    }
+
+    #[test]
+    fn keeps_positions_before_change() {
+        let shifted = shift_position(&at(3, 1), &at(0, 2), &at(4, 2), &at(0, 5));
+        assert!(shifted == at(3, 1));
+        let shifted = shift_position(&at(1, 2), &at(2, 2), &at(4, 2), &at(9, 2));
+        assert!(shifted == at(1, 2));
+    }
+
+    #[test]
+    fn moves_positions_inside_change_to_its_start() {
+        let shifted = shift_position(&at(3, 2), &at(2, 1), &at(1, 4), &at(5, 1));
+        assert!(shifted == at(2, 1));
+    }
+
+    #[test]
+    fn shifts_positions_on_last_changed_row() {
+        // "abcdef" with "cd" replaced by "x"
+        let shifted = shift_position(&at(5, 0), &at(2, 0), &at(4, 0), &at(3, 0));
+        assert!(shifted == at(4, 0));
+        // Newline inserted at column 2
+        let shifted = shift_position(&at(5, 0), &at(2, 0), &at(2, 0), &at(0, 1));
+        assert!(shifted == at(3, 1));
+    }
+
+    #[test]
+    fn shifts_rows_after_change() {
+        let shifted = shift_position(&at(7, 6), &at(2, 1), &at(0, 3), &at(4, 1));
+        assert!(shifted == at(7, 4));
+        let shifted = shift_position(&at(7, 6), &at(2, 1), &at(2, 1), &at(0, 3));
+        assert!(shifted == at(7, 8));
+    }
+
+    #[test]
+    fn edits_at_all_cursors() {
+        let mut document = document("ab ab");
+        let mut cursors = vec![at(1, 0), at(2, 0), at(1, 1)];
+        edit(&mut document, &mut cursors, Key::Char('x'), 4, false);
+        assert_eq!(document.lines(), ["axbx", "axb"]);
+        assert!(cursors == [at(2, 0), at(4, 0), at(2, 1)]);
+        edit(&mut document, &mut cursors, Key::Backspace, 4, false);
+        edit(&mut document, &mut cursors, Key::Backspace, 4, false);
+        assert_eq!(document.lines(), ["", "b"]);
+        assert!(cursors == [at(0, 0), at(0, 0), at(0, 1)]);
+    }
+
+    #[test]
+    fn adds_cursors_at_matches() {
+        let document = document("ab ab ab");
+        let ends = match_ends(&document, "ab", SearchOptions::default());
+        let mut cursors = Cursors::default();
+        let mut cursor = at(2, 1);
+        assert!(cursors.add_next_match(&mut cursor, ends.clone()));
+        assert!(cursor == at(2, 2));
+        assert!(cursors.add_next_match(&mut cursor, ends.clone()));
+        assert!(cursor == at(2, 0));
+        assert!(!cursors.add_next_match(&mut cursor, ends.clone()));
+        assert_eq!(cursors.count(), 3);
+        let mut cursors = Cursors::default();
+        let mut cursor = at(0, 1);
+        assert!(!cursors.add_all_matches(&mut cursor, Vec::new()));
+        assert!(cursors.add_all_matches(&mut cursor, ends));
+        assert!(cursor == at(2, 0));
+        assert!(cursors.all(&cursor).0 == [at(2, 0), at(2, 1), at(2, 2)]);
+    }
+
+    #[test]
+    fn adds_cursors_in_column() {
+        let document = document("abc a abc");
+        let mut cursors = Cursors::default();
+        let mut cursor = at(2, 0);
+        cursors.add_in_column(&mut cursor, &document, true);
+        assert!(cursor == at(1, 1));
+        cursors.add_in_column(&mut cursor, &document, true);
+        cursors.add_in_column(&mut cursor, &document, true);
+        assert!(cursors.all(&cursor).0 == [at(2, 0), at(1, 1), at(1, 2)]);
+        cursors.set_in_rows(&mut cursor, &document, 1..=2);
+        assert!(cursor == at(1, 2));
+        assert!(cursors.all(&cursor).0 == [at(1, 1), at(1, 2)]);
+    }
+}
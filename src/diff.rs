@@ -0,0 +1,566 @@
+use std::cmp;
+
+/// Edit distance above which the remaining difference is reported as one replaced block.
+/// It keeps time of Myers' algorithm bounded for very different sequences.
+const MAX_EDIT_DISTANCE: usize = 4096;
+
+/// One step of transformation of old sequence into new one
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Change {
+    /// Element at old index equals element at new index
+    Equal(usize, usize),
+    /// Element at old index was removed
+    Delete(usize),
+    /// Element at new index was added
+    Insert(usize),
+}
+
+/// Group of changes surrounded by up to `context` equal elements
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub changes: Vec<Change>,
+}
+
+/// Find the shortest sequence of changes transforming `old` into `new` (Myers' algorithm in linear space)
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    compare(old, new, (0, 0), &mut changes);
+    changes
+}
+
+/// Append changes transforming `old` into `new`, which start at indices `start` of the whole sequences
+fn compare<T: PartialEq>(old: &[T], new: &[T], start: (usize, usize), changes: &mut Vec<Change>) {
+    // Common prefix and suffix are cheap to find and usually make the rest much shorter
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|&(a, b)| a == b)
+        .count();
+    let old_rest = old.get(prefix..).unwrap_or_default();
+    let new_rest = new.get(prefix..).unwrap_or_default();
+    let suffix = old_rest
+        .iter()
+        .rev()
+        .zip(new_rest.iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count();
+    let (old_start, new_start) = start;
+    push_equal(changes, old_start, new_start, prefix);
+
+    let old_mid = old_rest
+        .get(..old_rest.len().saturating_sub(suffix))
+        .unwrap_or_default();
+    let new_mid = new_rest
+        .get(..new_rest.len().saturating_sub(suffix))
+        .unwrap_or_default();
+    let old_mid_start = old_start.saturating_add(prefix);
+    let new_mid_start = new_start.saturating_add(prefix);
+    let split = if old_mid.is_empty() || new_mid.is_empty() {
+        None
+    } else {
+        middle(old_mid, new_mid)
+    };
+    if let Some((x, y)) = split {
+        compare(
+            old_mid.get(..x).unwrap_or_default(),
+            new_mid.get(..y).unwrap_or_default(),
+            (old_mid_start, new_mid_start),
+            changes,
+        );
+        compare(
+            old_mid.get(x..).unwrap_or_default(),
+            new_mid.get(y..).unwrap_or_default(),
+            (
+                old_mid_start.saturating_add(x),
+                new_mid_start.saturating_add(y),
+            ),
+            changes,
+        );
+    } else {
+        changes.extend(
+            (0..old_mid.len()).map(|index| Change::Delete(old_mid_start.saturating_add(index))),
+        );
+        changes.extend(
+            (0..new_mid.len()).map(|index| Change::Insert(new_mid_start.saturating_add(index))),
+        );
+    }
+
+    push_equal(
+        changes,
+        old_mid_start.saturating_add(old_mid.len()),
+        new_mid_start.saturating_add(new_mid.len()),
+        suffix,
+    );
+}
+
+/// Append `len` equal elements starting at `old_start` and `new_start`
+fn push_equal(changes: &mut Vec<Change>, old_start: usize, new_start: usize, len: usize) {
+    changes.extend((0..len).map(|index| {
+        Change::Equal(
+            old_start.saturating_add(index),
+            new_start.saturating_add(index),
+        )
+    }));
+}
+
+/// Furthest x reached on each diagonal `k = x - y` of edit graph. -1 marks diagonals which weren't
+/// reached yet.
+struct Diagonals {
+    furthest: Vec<isize>,
+    /// Index of diagonal 0
+    offset: isize,
+}
+
+impl Diagonals {
+    /// Diagonals reachable by `max` edits, the start is reached without any edit
+    fn new(max: isize) -> Self {
+        let len = usize::try_from(max.saturating_mul(2).saturating_add(3)).unwrap_or(0);
+        let mut diagonals = Self {
+            furthest: vec![-1; len],
+            offset: max.saturating_add(1),
+        };
+        diagonals.set(1, 0);
+        diagonals
+    }
+
+    fn index(&self, k: isize) -> Option<usize> {
+        usize::try_from(k.saturating_add(self.offset)).ok()
+    }
+
+    fn get(&self, k: isize) -> isize {
+        self.index(k)
+            .and_then(|index| self.furthest.get(index))
+            .copied()
+            .unwrap_or(-1)
+    }
+
+    fn set(&mut self, k: isize, x: isize) {
+        if let Some(furthest) = self.index(k).and_then(|index| self.furthest.get_mut(index)) {
+            *furthest = x;
+        }
+    }
+
+    /// Furthest x on diagonal `k` after `edits` edits, before following equal elements.
+    /// It's reached from the neighbouring diagonal which got further.
+    fn step(&self, k: isize, edits: isize) -> isize {
+        let (below, above) = (self.get(k.saturating_sub(1)), self.get(k.saturating_add(1)));
+        if k == edits.saturating_neg() || (k != edits && below < above) {
+            above
+        } else {
+            below.saturating_add(1)
+        }
+    }
+}
+
+/// Element of `items` at `index`, `None` outside of them
+fn element<T>(items: &[T], index: isize) -> Option<&T> {
+    usize::try_from(index)
+        .ok()
+        .and_then(|index| items.get(index))
+}
+
+/// Point where the shortest edit paths from the start and from the end of non-empty `old` and `new`
+/// meet, which splits the difference into two halves. Only the furthest point on every diagonal is
+/// kept, so memory is linear. `None` when edit distance exceeds `MAX_EDIT_DISTANCE`.
+fn middle<T: PartialEq>(old: &[T], new: &[T]) -> Option<(usize, usize)> {
+    let old_len = isize::try_from(old.len()).ok()?;
+    let new_len = isize::try_from(new.len()).ok()?;
+    let delta = old_len.saturating_sub(new_len);
+    // Paths from both ends meet when the forward one moves on diagonals of odd difference
+    let front = delta.checked_rem(2) != Some(0);
+    let max = old_len
+        .saturating_add(new_len)
+        .saturating_add(1)
+        .checked_div(2)?
+        .min(isize::try_from(MAX_EDIT_DISTANCE.checked_div(2)?).ok()?);
+    // Backward diagonals are counted from the end of both sequences
+    let mut forward = Diagonals::new(max);
+    let mut backward = Diagonals::new(max);
+    // Diagonals which ran out of `old` or `new` are skipped in later steps
+    let (mut forward_start, mut forward_end): (isize, isize) = (0, 0);
+    let (mut backward_start, mut backward_end): (isize, isize) = (0, 0);
+    for edits in 0..=max {
+        let mut k = forward_start.saturating_sub(edits);
+        while k <= edits.saturating_sub(forward_end) {
+            let mut x = forward.step(k, edits);
+            let mut y = x.saturating_sub(k);
+            while x < old_len && y < new_len && element(old, x) == element(new, y) {
+                x = x.saturating_add(1);
+                y = y.saturating_add(1);
+            }
+            forward.set(k, x);
+            if x > old_len {
+                forward_end = forward_end.saturating_add(2);
+            } else if y > new_len {
+                forward_start = forward_start.saturating_add(2);
+            } else if front {
+                let reached = backward.get(delta.saturating_sub(k));
+                if reached != -1 && x >= old_len.saturating_sub(reached) {
+                    return Some((usize::try_from(x).ok()?, usize::try_from(y).ok()?));
+                }
+            }
+            k = k.saturating_add(2);
+        }
+
+        let mut reverse_k = backward_start.saturating_sub(edits);
+        while reverse_k <= edits.saturating_sub(backward_end) {
+            let mut x = backward.step(reverse_k, edits);
+            let mut y = x.saturating_sub(reverse_k);
+            while x < old_len
+                && y < new_len
+                && element(old, old_len.saturating_sub(x).saturating_sub(1))
+                    == element(new, new_len.saturating_sub(y).saturating_sub(1))
+            {
+                x = x.saturating_add(1);
+                y = y.saturating_add(1);
+            }
+            backward.set(reverse_k, x);
+            if x > old_len {
+                backward_end = backward_end.saturating_add(2);
+            } else if y > new_len {
+                backward_start = backward_start.saturating_add(2);
+            } else if !front {
+                let forward_k = delta.saturating_sub(reverse_k);
+                let forward_x = forward.get(forward_k);
+                if forward_x != -1 && forward_x >= old_len.saturating_sub(x) {
+                    return Some((
+                        usize::try_from(forward_x).ok()?,
+                        usize::try_from(forward_x.saturating_sub(forward_k)).ok()?,
+                    ));
+                }
+            }
+            reverse_k = reverse_k.saturating_add(2);
+        }
+    }
+    None
+}
+
+/// Split changes into hunks with `context` equal elements around every modification
+pub fn hunks(changes: &[Change], context: usize) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let modified: Vec<usize> = changes
+        .iter()
+        .enumerate()
+        .filter(|&(_, change)| !matches!(*change, Change::Equal(..)))
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for index in modified {
+        let start = index.saturating_sub(context);
+        let end = cmp::min(
+            index.saturating_add(context).saturating_add(1),
+            changes.len(),
+        );
+        if let Some(last) = groups.last_mut() {
+            if start <= last.1 {
+                last.1 = end;
+                continue;
+            }
+        }
+        groups.push((start, end));
+    }
+
+    for (start, end) in groups {
+        let slice = changes.get(start..end).unwrap_or_default();
+        let (old_start, new_start) = starts(changes, start);
+        let old_len = slice
+            .iter()
+            .filter(|change| !matches!(**change, Change::Insert(_)))
+            .count();
+        let new_len = slice
+            .iter()
+            .filter(|change| !matches!(**change, Change::Delete(_)))
+            .count();
+        hunks.push(Hunk {
+            old_start,
+            old_len,
+            new_start,
+            new_len,
+            changes: slice.to_vec(),
+        });
+    }
+    hunks
+}
+
+/// Render changes between `old` and `new` lines in unified diff format
+pub fn unified(old_name: &str, new_name: &str, old: &[String], new: &[String]) -> Vec<String> {
+    let changes = diff(old, new);
+    let mut lines = Vec::new();
+    let hunks = hunks(&changes, 3);
+    if hunks.is_empty() {
+        return lines;
+    }
+    lines.push(format!("--- {}", old_name));
+    lines.push(format!("+++ {}", new_name));
+    for hunk in hunks {
+        lines.push(hunk.header());
+        for change in hunk.changes {
+            let line = match change {
+                Change::Equal(index, _) => format!(" {}", line_at(old, index)),
+                Change::Delete(index) => format!("-{}", line_at(old, index)),
+                Change::Insert(index) => format!("+{}", line_at(new, index)),
+            };
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// Index of line in new file which line `y` of unified diff `lines` refers to.
+/// Removed lines refer to the line which follows them in new file.
+pub fn new_line_at(lines: &[String], y: usize) -> Option<usize> {
+    let mut new_line: Option<usize> = None;
+    for line in lines.iter().take(y.saturating_add(1)) {
+        if line.starts_with("@@") {
+            // `@@ -1,4 +1,5 @@`, start of new range is one-based unless the range is empty
+            let new_range = line.split(' ').find(|field| field.starts_with('+'))?;
+            let mut parts = new_range.trim_start_matches('+').split(',');
+            let start: usize = parts.next()?.parse().ok()?;
+            let len: usize = parts.next().map_or(Some(1), |len| len.parse().ok())?;
+            new_line = Some(if len == 0 {
+                start
+            } else {
+                start.saturating_sub(1)
+            });
+        } else if let Some(index) = new_line {
+            if !line.starts_with('-') {
+                new_line = Some(index.saturating_add(1));
+            }
+        }
+    }
+    // Line `y` itself was counted when it's context or added line
+    match lines.get(y) {
+        Some(line) if !line.starts_with("@@") && !line.starts_with('-') => {
+            new_line.map(|index| index.saturating_sub(1))
+        }
+        _ => new_line,
+    }
+}
+
+impl Hunk {
+    /// Header in unified diff format with one-based line numbers, e.g. `@@ -1,4 +1,5 @@`
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_len),
+            range(self.new_start, self.new_len)
+        )
+    }
+}
+
+fn range(start: usize, len: usize) -> String {
+    if len == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start.saturating_add(1), len)
+    }
+}
+
+fn line_at(lines: &[String], index: usize) -> &str {
+    lines.get(index).map_or("", String::as_str)
+}
+
+/// Indexes in old and new sequence where change at `index` starts
+fn starts(changes: &[Change], index: usize) -> (usize, usize) {
+    let mut old: usize = 0;
+    let mut new: usize = 0;
+    for change in changes.iter().take(index) {
+        match *change {
+            Change::Equal(..) => {
+                old = old.saturating_add(1);
+                new = new.saturating_add(1);
+            }
+            Change::Delete(_) => old = old.saturating_add(1),
+            Change::Insert(_) => new = new.saturating_add(1),
+        }
+    }
+    (old, new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split(' ').map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn equal_sequences() {
+        let old = lines("a b c");
+        assert_eq!(
+            diff(&old, &old),
+            vec![
+                Change::Equal(0, 0),
+                Change::Equal(1, 1),
+                Change::Equal(2, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn replaced_element() {
+        assert_eq!(
+            diff(&lines("a b c"), &lines("a x c")),
+            vec![
+                Change::Equal(0, 0),
+                Change::Delete(1),
+                Change::Insert(1),
+                Change::Equal(2, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn inserted_and_deleted_elements() {
+        assert_eq!(
+            diff(&lines("a b c d"), &lines("x a c d y")),
+            vec![
+                Change::Insert(0),
+                Change::Equal(0, 1),
+                Change::Delete(1),
+                Change::Equal(2, 2),
+                Change::Equal(3, 3),
+                Change::Insert(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_sequences() {
+        let empty: Vec<String> = Vec::new();
+        assert_eq!(diff(&empty, &empty), vec![]);
+        assert_eq!(
+            diff(&empty, &lines("a b")),
+            vec![Change::Insert(0), Change::Insert(1)]
+        );
+        assert_eq!(
+            diff(&lines("a b"), &empty),
+            vec![Change::Delete(0), Change::Delete(1)]
+        );
+    }
+
+    #[test]
+    fn hunks_with_context() {
+        let changes = diff(&lines("a b c d e f g h"), &lines("a b c x e f g h"));
+        let hunks = hunks(&changes, 1);
+        assert_eq!(hunks.len(), 1);
+        let hunk = &hunks[0];
+        assert_eq!(
+            (hunk.old_start, hunk.old_len, hunk.new_start, hunk.new_len),
+            (2, 3, 2, 3)
+        );
+        assert_eq!(hunk.header(), "@@ -3,3 +3,3 @@");
+    }
+
+    #[test]
+    fn close_changes_share_hunk() {
+        let old = lines("a b c d e f g h i j");
+        let changes = diff(&old, &lines("x b c d e f g h i y"));
+        assert_eq!(hunks(&changes, 1).len(), 2);
+        assert_eq!(hunks(&changes, 4).len(), 1);
+        assert!(hunks(&diff(&old, &old), 3).is_empty());
+    }
+
+    #[test]
+    fn header_of_empty_range() {
+        let changes = diff(&lines("a b"), &lines("a x b"));
+        let hunks = hunks(&changes, 0);
+        assert_eq!(hunks[0].header(), "@@ -1,0 +2,1 @@");
+    }
+
+    #[test]
+    fn unified_diff() {
+        let unified = unified("old", "new", &lines("a b c"), &lines("a x c"));
+        assert_eq!(
+            unified,
+            vec![
+                "--- old",
+                "+++ new",
+                "@@ -1,3 +1,3 @@",
+                " a",
+                "-b",
+                "+x",
+                " c"
+            ]
+        );
+        assert_eq!(new_line_at(&unified, 2), Some(0));
+        assert_eq!(new_line_at(&unified, 3), Some(0));
+        assert_eq!(new_line_at(&unified, 4), Some(1));
+        assert_eq!(new_line_at(&unified, 5), Some(1));
+        assert_eq!(new_line_at(&unified, 6), Some(2));
+        assert_eq!(new_line_at(&unified, 0), None);
+    }
+
+    /// Length of the longest common subsequence by dynamic programming
+    fn common_len(old: &[u8], new: &[u8]) -> usize {
+        let mut previous = vec![0_usize; new.len() + 1];
+        for a in old {
+            let mut current = vec![0_usize; new.len() + 1];
+            for (j, b) in new.iter().enumerate() {
+                current[j + 1] = if a == b {
+                    previous[j] + 1
+                } else {
+                    current[j].max(previous[j + 1])
+                };
+            }
+            previous = current;
+        }
+        previous[new.len()]
+    }
+
+    #[test]
+    fn finds_shortest_changes() {
+        // Linear congruential generator, sequences of a small alphabet have many common elements
+        let mut seed: u32 = 1;
+        let mut random = |limit: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) % limit
+        };
+        for _ in 0..300 {
+            let old: Vec<u8> = (0..random(40)).map(|_| random(4) as u8).collect();
+            let new: Vec<u8> = (0..random(40)).map(|_| random(4) as u8).collect();
+            let changes = diff(&old, &new);
+            let (mut x, mut y) = (0, 0);
+            for change in &changes {
+                match *change {
+                    Change::Equal(i, j) => {
+                        assert_eq!((i, j), (x, y));
+                        assert_eq!(old[i], new[j]);
+                        x += 1;
+                        y += 1;
+                    }
+                    Change::Delete(i) => {
+                        assert_eq!(i, x);
+                        x += 1;
+                    }
+                    Change::Insert(j) => {
+                        assert_eq!(j, y);
+                        y += 1;
+                    }
+                }
+            }
+            assert_eq!((x, y), (old.len(), new.len()));
+            let equal = changes
+                .iter()
+                .filter(|change| matches!(change, Change::Equal(..)))
+                .count();
+            assert_eq!(equal, common_len(&old, &new));
+        }
+    }
+
+    #[test]
+    fn replaces_very_different_sequences() {
+        let old: Vec<usize> = (0..MAX_EDIT_DISTANCE).collect();
+        let new: Vec<usize> = (MAX_EDIT_DISTANCE..2 * MAX_EDIT_DISTANCE + 2).collect();
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), old.len() + new.len());
+        assert!(changes[..old.len()]
+            .iter()
+            .all(|change| matches!(change, Change::Delete(_))));
+    }
+}
@@ -1,11 +1,136 @@
 use std::{
+    cmp,
+    collections::HashMap,
     fs,
     io::{self, Write},
+    os::unix::fs::MetadataExt,
+    path::Path,
+    time::SystemTime,
 };
 
+use crate::browser::Listing;
+use crate::git::{BlameLine, Changes};
+use crate::highlighting;
 use crate::FileType;
 use crate::SearchDirection;
+use crate::SearchOptions;
 use crate::{Position, Row};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Number of bytes shown on one row of the hex view
+const HEX_BYTES_PER_ROW: usize = 16;
+/// Width of the offset column in the hex view including the following spaces, e.g. `0000001f  `
+const HEX_OFFSET_WIDTH: usize = 10;
+/// Number of spaces in one level of indentation
+const INDENT_WIDTH: usize = 4;
+
+/// File metadata used to detect changes of file made by other programs
+#[derive(PartialEq, Clone, Copy)]
+struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+    inode: u64,
+}
+
+impl DiskState {
+    fn of(file_name: &str) -> Option<Self> {
+        let metadata = fs::metadata(file_name).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            inode: metadata.ino(),
+        })
+    }
+}
+
+/// Merge conflict left in file e.g. by rebase, with rows of its markers:
+/// `<<<<<<<` ours `|||||||` base `=======` theirs `>>>>>>>`
+#[derive(Clone)]
+pub struct Conflict {
+    pub start: usize,
+    /// Base section is present in the diff3 conflict style only
+    pub base: Option<usize>,
+    pub separator: usize,
+    pub end: usize,
+}
+
+/// Sections of merge conflict kept by its resolution
+#[derive(Clone, Copy)]
+pub enum Resolution {
+    Ours,
+    Theirs,
+    Both,
+}
+
+/// Indentation style of document, detected from its rows when it's opened
+#[derive(Clone, Copy, PartialEq)]
+pub enum Indentation {
+    Tabs,
+    Spaces(usize),
+}
+
+impl Default for Indentation {
+    fn default() -> Self {
+        Self::Spaces(INDENT_WIDTH)
+    }
+}
+
+impl Indentation {
+    /// Style used by most indented rows: tabs, or spaces with the most common increase of indentation
+    /// between successive rows. `None` when no row is indented.
+    fn detect(rows: &[Row]) -> Option<Self> {
+        let mut tab_rows: usize = 0;
+        let mut space_rows: usize = 0;
+        let mut increases: HashMap<usize, usize> = HashMap::new();
+        let mut previous: usize = 0;
+        for row in rows.iter().filter(|row| !row.is_blank()) {
+            let line = row.get_string();
+            if line.starts_with('\t') {
+                tab_rows = tab_rows.saturating_add(1);
+                continue;
+            }
+            let spaces = line
+                .len()
+                .saturating_sub(line.trim_start_matches(' ').len());
+            if spaces > 0 {
+                space_rows = space_rows.saturating_add(1);
+            }
+            // Increase by one space is rather an alignment, e.g. of a block comment
+            let increase = spaces.saturating_sub(previous);
+            if (2..=8).contains(&increase) {
+                let count = increases.entry(increase).or_default();
+                *count = count.saturating_add(1);
+            }
+            previous = spaces;
+        }
+        if tab_rows == 0 && space_rows == 0 {
+            None
+        } else if tab_rows > space_rows {
+            Some(Self::Tabs)
+        } else {
+            increases
+                .into_iter()
+                .max_by_key(|&(width, count)| (count, cmp::Reverse(width)))
+                .map(|(width, _)| Self::Spaces(width))
+        }
+    }
+
+    /// Text of one level of indentation
+    pub fn unit(self) -> String {
+        match self {
+            Self::Tabs => "\t".to_owned(),
+            Self::Spaces(width) => " ".repeat(width),
+        }
+    }
+
+    /// Name shown in status bar, e.g. `Spaces: 4`
+    pub fn name(self) -> String {
+        match self {
+            Self::Tabs => "Tabs".to_owned(),
+            Self::Spaces(width) => format!("Spaces: {}", width),
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct Document {
@@ -13,6 +138,47 @@ pub struct Document {
     pub file_name: Option<String>,
     dirty: bool,
     file_type: FileType,
+    /// Raw content of a binary file opened in hex view. Rows are only a rendered representation of these bytes.
+    bytes: Option<Vec<u8>>,
+    /// State of file when it was opened or saved
+    disk_state: Option<DiskState>,
+    /// State of file when user was asked about its change for the last time
+    seen_disk_state: Option<DiskState>,
+    read_only: bool,
+    /// Entries of directory shown by the file browser
+    listing: Option<Listing>,
+    /// Locations in files which rows of a location list (e.g. search results) point to
+    locations: Option<Vec<Option<(String, Position)>>>,
+    /// Incremented by every change of content
+    revision: usize,
+    /// Changes against the version of file committed in git
+    git_changes: Option<Changes>,
+    /// Commits which last changed rows, with revision of document they were found for
+    blame: Option<(usize, Vec<Option<BlameLine>>)>,
+    /// Complete merge conflicts, with revision of document they were found for
+    conflicts: Option<(usize, Vec<Conflict>)>,
+    indentation_style: Indentation,
+}
+
+impl Conflict {
+    pub fn contains(&self, y: usize) -> bool {
+        self.start <= y && y <= self.end
+    }
+
+    /// Highlighting of row `y` according to the section of conflict it belongs to
+    fn section(&self, y: usize) -> Option<highlighting::Type> {
+        if !self.contains(y) {
+            None
+        } else if y == self.start || y == self.separator || y == self.end || Some(y) == self.base {
+            Some(highlighting::Type::ConflictMarker)
+        } else if y > self.separator {
+            Some(highlighting::Type::ConflictTheirs)
+        } else if self.base.is_some_and(|base| y > base) {
+            Some(highlighting::Type::ConflictBase)
+        } else {
+            Some(highlighting::Type::ConflictOurs)
+        }
+    }
 }
 
 impl Document {
@@ -20,8 +186,16 @@ impl Document {
     ///
     /// Will return `Err` if `filename` does not exist or the user does not have
     /// permission to read it.
+    /// Binary files (invalid UTF-8 or containing NUL characters) are refused with `ErrorKind::InvalidData`,
+    /// use `Document::open_hex` for them.
     pub fn open(filename: &str) -> io::Result<Self> {
         let contents = fs::read_to_string(filename)?;
+        if contents.contains('\0') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid text",
+            ));
+        }
         let file_type = FileType::from(filename);
         let mut rows = Vec::new();
         for value in contents.lines() {
@@ -34,26 +208,238 @@ impl Document {
             rows.push(Row::from(""));
         }
 
+        let disk_state = DiskState::of(filename);
+        let indentation_style = Indentation::detect(&rows).unwrap_or_default();
         Ok(Self {
             rows,
             file_name: Some(filename.to_owned()),
             dirty: false,
             file_type,
+            bytes: None,
+            disk_state,
+            seen_disk_state: disk_state,
+            read_only: false,
+            listing: None,
+            locations: None,
+            revision: 0,
+            git_changes: None,
+            blame: None,
+            conflicts: None,
+            indentation_style,
         })
     }
 
+    /// Open any file as a sequence of bytes shown in hex view
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if `filename` does not exist or the user does not have
+    /// permission to read it.
+    pub fn open_hex(filename: &str) -> io::Result<Self> {
+        let bytes = fs::read(filename)?;
+        let disk_state = DiskState::of(filename);
+        let mut document = Self {
+            rows: Vec::new(),
+            file_name: Some(filename.to_owned()),
+            dirty: false,
+            file_type: FileType::hex(),
+            bytes: Some(bytes),
+            disk_state,
+            seen_disk_state: disk_state,
+            read_only: false,
+            listing: None,
+            locations: None,
+            revision: 0,
+            git_changes: None,
+            blame: None,
+            conflicts: None,
+            indentation_style: Indentation::default(),
+        };
+        document.render_hex_rows(0);
+        Ok(document)
+    }
+
+    /// Create read-only document not backed by any file, e.g. for showing of diff
+    pub fn view(title: &str, lines: &[String]) -> Self {
+        Self {
+            rows: lines.iter().map(|line| Row::from(line.as_str())).collect(),
+            file_name: Some(title.to_owned()),
+            read_only: true,
+            ..Self::default()
+        }
+    }
+
+    /// Read-only listing of `directory` for the file browser
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if `directory` cannot be read
+    pub fn open_directory(directory: &Path) -> io::Result<Self> {
+        let listing = Listing::read(directory)?;
+        let mut document = Self::view(&directory.to_string_lossy(), &listing.lines());
+        document.listing = Some(listing);
+        Ok(document)
+    }
+
+    /// Empty read-only list of locations in files, e.g. search results
+    pub fn location_list(title: &str) -> Self {
+        let mut document = Self::view(title, &[]);
+        document.locations = Some(Vec::new());
+        document
+    }
+
+    /// Append row to location list, pointing to position in file
+    pub fn push_location(&mut self, line: &str, location: Option<(String, Position)>) {
+        if let Some(ref mut locations) = self.locations {
+            self.rows.push(Row::from(line));
+            locations.push(location);
+        }
+    }
+
+    pub fn is_location_list(&self) -> bool {
+        self.locations.is_some()
+    }
+
+    /// File name and position which row `y` of location list points to
+    pub fn location(&self, y: usize) -> Option<&(String, Position)> {
+        self.locations.as_ref()?.get(y)?.as_ref()
+    }
+
+    /// Directory listing if the document is shown by the file browser
+    pub fn listing(&self) -> Option<&Listing> {
+        self.listing.as_ref()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Content of all rows as they are shown
+    pub fn lines(&self) -> Vec<String> {
+        self.rows.iter().map(Row::get_string).collect()
+    }
+
+    /// Whether the file was changed by another program since it was opened or saved
+    pub fn changed_on_disk(&self) -> bool {
+        match (&self.file_name, self.disk_state) {
+            (Some(file_name), Some(disk_state)) => DiskState::of(file_name) != Some(disk_state),
+            _ => false,
+        }
+    }
+
+    /// Whether the file was changed by another program and user hasn't been asked about this change yet
+    pub fn poll_disk_change(&self) -> bool {
+        if let Some(ref file_name) = self.file_name {
+            if self.disk_state.is_some() {
+                let current = DiskState::of(file_name);
+                return current != self.disk_state && current != self.seen_disk_state;
+            }
+        }
+        false
+    }
+
+    /// Remember current state of file so that its change isn't reported again
+    pub fn mark_disk_change_seen(&mut self) {
+        if let Some(ref file_name) = self.file_name {
+            self.seen_disk_state = DiskState::of(file_name);
+        }
+    }
+
+    /// Number which changes with every change of content
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    /// Set lines of the committed version of file, which rows are compared with
+    pub fn set_git_base(&mut self, base: Option<Vec<String>>) {
+        self.git_changes = base.map(Changes::new);
+    }
+
+    /// Compare rows with the committed version of file again if they changed
+    pub fn update_git_changes(&mut self) {
+        if let Some(ref mut changes) = self.git_changes {
+            changes.update(&self.rows, self.revision);
+        }
+    }
+
+    /// Changes against the committed version of file, `None` if the file isn't committed in git
+    pub fn git_changes(&self) -> Option<&Changes> {
+        self.git_changes.as_ref()
+    }
+
+    /// Set commits which last changed rows of current content, `None` turns blame off
+    pub fn set_blame(&mut self, blame: Option<Vec<Option<BlameLine>>>) {
+        self.blame = blame.map(|lines| (self.revision, lines));
+    }
+
+    pub fn blame(&self) -> Option<&[Option<BlameLine>]> {
+        self.blame.as_ref().map(|(_, lines)| lines.as_slice())
+    }
+
+    /// Blame is shown but content changed since it was found
+    pub fn is_blame_outdated(&self) -> bool {
+        matches!(self.blame, Some((revision, _)) if revision != self.revision)
+    }
+
     pub fn file_type(&self) -> String {
         self.file_type.name()
     }
 
+    pub fn set_file_type(&mut self, file_type: FileType) {
+        self.file_type = file_type;
+        self.unhighlight_rows(0);
+    }
+
     pub fn row(&self, index: usize) -> Option<&Row> {
         self.rows.get(index)
     }
 
+    /// Length of row in graphemes. In hex view it's the last byte index of row,
+    /// the last row allows one more position for appending of bytes.
     pub fn row_len(&self, y: usize) -> usize {
+        if let Some(ref bytes) = self.bytes {
+            return if y.saturating_add(1) < self.len() {
+                HEX_BYTES_PER_ROW.saturating_sub(1)
+            } else {
+                bytes.len().checked_rem(HEX_BYTES_PER_ROW).unwrap_or(0)
+            };
+        }
         self.row(y).map_or(0, Row::len)
     }
 
+    /// Column on screen where the cursor at position `at` is drawn
+    pub fn render_x(&self, at: &Position, tab_width: usize) -> usize {
+        if self.is_hex() {
+            HEX_OFFSET_WIDTH.saturating_add(at.x.saturating_mul(3))
+        } else {
+            self.row(at.y)
+                .map_or(0, |row| row.width_until(at.x, tab_width))
+        }
+    }
+
+    /// Position of grapheme (or byte in hex view) drawn at screen `column` of row `y`
+    pub fn position_at(&self, y: usize, column: usize, tab_width: usize) -> Position {
+        let y = y.min(self.len().saturating_sub(1));
+        let x = if self.is_hex() {
+            let ascii_start = HEX_OFFSET_WIDTH
+                .saturating_add(HEX_BYTES_PER_ROW.saturating_mul(3))
+                .saturating_add(2);
+            let x = if let Some(x) = column.checked_sub(ascii_start) {
+                x
+            } else {
+                column
+                    .saturating_sub(HEX_OFFSET_WIDTH)
+                    .checked_div(3)
+                    .unwrap_or(0)
+            };
+            x.min(self.row_len(y))
+        } else {
+            self.row(y)
+                .map_or(0, |row| row.x_at_column(column, tab_width))
+        };
+        Position { x, y }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.rows.is_empty()
     }
@@ -62,37 +448,311 @@ impl Document {
         self.rows.len()
     }
 
-    fn insert_newline(&mut self, at: &Position) {
+    /// Split row at `at` and return start of text on the new row. The new row keeps indentation of
+    /// the split one, one level more after a character which indents next line.
+    fn insert_newline(&mut self, at: &Position) -> Position {
         if self.is_empty() {
             // Empty document will have two new lines
             self.rows.push(Row::default());
             self.rows.push(Row::default());
+            return Position { x: 0, y: 1 };
+        }
+        #[allow(clippy::indexing_slicing)]
+        let current_row = &mut self.rows[at.y];
+        let after = current_row.split(at.x).get_string();
+        let before = current_row.get_string();
+        // Splitting the indentation itself doesn't indent the text after cursor more
+        let indentation: String = before.chars().take_while(|c| c.is_whitespace()).collect();
+        let rules = self.file_type.indentation_rules();
+        let opened = before
+            .trim_end()
+            .chars()
+            .last()
+            .is_some_and(|c| rules.indents_after(c));
+        let closed = after
+            .trim_start()
+            .chars()
+            .next()
+            .is_some_and(|c| rules.dedents_on(c));
+        let mut lines = Vec::new();
+        let text_start = if opened {
+            let inner = format!("{}{}", indentation, self.indent_unit());
+            if closed {
+                // Closing bracket right after the opening one goes to its own line
+                lines.push(inner.clone());
+                lines.push(format!("{}{}", indentation, after.trim_start()));
+            } else {
+                lines.push(format!("{}{}", inner, after));
+            }
+            inner.graphemes(true).count()
         } else {
-            #[allow(clippy::indexing_slicing)]
-            let current_row = &mut self.rows[at.y];
-            let new_row = current_row.split(at.x);
-            #[allow(clippy::integer_arithmetic)]
-            self.rows.insert(at.y + 1, new_row);
+            lines.push(format!("{}{}", indentation, after));
+            indentation.graphemes(true).count()
+        };
+        let next = at.y.saturating_add(1);
+        self.rows.splice(
+            next..next,
+            lines.iter().map(|line| Row::from(line.as_str())),
+        );
+        Position {
+            x: text_start,
+            y: at.y.saturating_add(1),
         }
     }
 
-    pub fn insert(&mut self, at: &Position, c: char) {
+    /// Split row at `at` and return start of the new row
+    fn split_row(&mut self, at: &Position) -> Position {
+        if self.is_empty() {
+            self.rows.push(Row::default());
+        }
+        if let Some(row) = self.rows.get_mut(at.y) {
+            let new_row = row.split(at.x);
+            self.rows.insert(at.y.saturating_add(1), new_row);
+        }
+        Position {
+            x: 0,
+            y: at.y.saturating_add(1),
+        }
+    }
+
+    /// Leading whitespace of row `y`
+    fn indentation(&self, y: usize) -> String {
+        self.row(y).map_or_else(String::new, |row| {
+            row.get_string()
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .collect()
+        })
+    }
+
+    /// One level of indentation in the style of document
+    fn indent_unit(&self) -> String {
+        self.indentation_style.unit()
+    }
+
+    /// Indentation of the row with unclosed opening character before row `y`, which is matched by
+    /// a closing character at the start of row `y`
+    fn opener_indentation(&self, y: usize) -> Option<String> {
+        let rules = self.file_type.indentation_rules();
+        let mut depth: usize = 0;
+        for opener_y in (0..y).rev() {
+            for c in self.row(opener_y)?.get_string().chars().rev() {
+                if rules.dedents_on(c) {
+                    depth = depth.saturating_add(1);
+                } else if rules.indents_after(c) {
+                    if depth == 0 {
+                        return Some(self.indentation(opener_y));
+                    }
+                    depth = depth.saturating_sub(1);
+                }
+            }
+        }
+        None
+    }
+
+    /// `indentation` with the last level removed
+    fn dedent(&self, indentation: &str) -> String {
+        let mut dedented = indentation.to_owned();
+        if dedented.ends_with('\t') {
+            dedented.pop();
+            return dedented;
+        }
+        let width = match self.indentation_style {
+            Indentation::Spaces(width) => width.max(1),
+            Indentation::Tabs => INDENT_WIDTH,
+        };
+        let spaces = dedented
+            .len()
+            .saturating_sub(dedented.trim_end_matches(' ').len());
+        // Spaces are removed to the previous multiple of the indentation width
+        let remove = spaces
+            .saturating_sub(1)
+            .checked_rem(width)
+            .unwrap_or(0)
+            .saturating_add(1);
+        dedented.truncate(dedented.len().saturating_sub(remove.min(spaces)));
+        dedented
+    }
+
+    pub fn indentation_style(&self) -> Indentation {
+        self.indentation_style
+    }
+
+    /// Change indentation style used for new indentation, existing rows are kept
+    pub fn set_indentation_style(&mut self, indentation: Indentation) {
+        self.indentation_style = indentation;
+    }
+
+    /// Add one level of indentation to row `y`, returns the number of added graphemes
+    pub fn indent_row(&mut self, y: usize) -> usize {
+        let unit = self.indent_unit();
+        let line = self.row(y).map(Row::get_string);
+        if let (Some(line), Some(row)) = (line, self.rows.get_mut(y)) {
+            *row = Row::from(format!("{}{}", unit, line).as_str());
+            self.dirty = true;
+            self.revision = self.revision.wrapping_add(1);
+            self.unhighlight_rows(y);
+            unit.graphemes(true).count()
+        } else {
+            0
+        }
+    }
+
+    /// Remove one level of indentation from row `y`, returns the number of removed graphemes
+    pub fn dedent_row(&mut self, y: usize) -> usize {
+        let indentation = self.indentation(y);
+        let dedented = self.dedent(&indentation);
+        let removed = indentation
+            .graphemes(true)
+            .count()
+            .saturating_sub(dedented.graphemes(true).count());
+        if removed == 0 {
+            return 0;
+        }
+        let line = self.row(y).map(Row::get_string);
+        if let (Some(line), Some(row)) = (line, self.rows.get_mut(y)) {
+            *row = Row::from(
+                format!(
+                    "{}{}",
+                    dedented,
+                    line.get(indentation.len()..).unwrap_or_default()
+                )
+                .as_str(),
+            );
+            self.dirty = true;
+            self.revision = self.revision.wrapping_add(1);
+            self.unhighlight_rows(y);
+        }
+        removed
+    }
+
+    /// Rewrite indentation of all rows in `indentation` style and use it from now on. Levels of current
+    /// indentation are found by `tab_width` for tabs, alignment after them is kept as spaces.
+    pub fn convert_indentation(&mut self, indentation: Indentation, tab_width: usize) {
+        let width = match self.indentation_style {
+            Indentation::Spaces(width) => width,
+            Indentation::Tabs => tab_width,
+        }
+        .max(1);
+        let mut changed = false;
+        for row in &mut self.rows {
+            let line = row.get_string();
+            let text = line.trim_start_matches([' ', '\t']);
+            let leading = line
+                .get(..line.len().saturating_sub(text.len()))
+                .unwrap_or_default();
+            let columns = leading.chars().fold(0, |column: usize, c| {
+                if c == '\t' {
+                    column.saturating_add(
+                        width.saturating_sub(column.checked_rem(width).unwrap_or(0)),
+                    )
+                } else {
+                    column.saturating_add(1)
+                }
+            });
+            let levels = columns.checked_div(width).unwrap_or(0);
+            let rest = columns.checked_rem(width).unwrap_or(0);
+            let converted = match indentation {
+                Indentation::Tabs => format!("{}{}", "\t".repeat(levels), " ".repeat(rest)),
+                Indentation::Spaces(new_width) => {
+                    " ".repeat(levels.saturating_mul(new_width).saturating_add(rest))
+                }
+            };
+            if converted != leading {
+                *row = Row::from(format!("{}{}", converted, text).as_str());
+                changed = true;
+            }
+        }
+        self.indentation_style = indentation;
+        if changed {
+            self.dirty = true;
+            self.revision = self.revision.wrapping_add(1);
+            self.unhighlight_rows(0);
+        }
+    }
+
+    /// Insert `c` at position `at` and return position after it, where the cursor moves.
+    /// Closing character typed at the start of row gets indentation of the row with the opening one.
+    pub fn insert(&mut self, at: &Position, c: char) -> Position {
+        self.insert_char(at, c, true)
+    }
+
+    /// Insert tab at position `at` in indentation style of document, spaces fill up to the next multiple
+    /// of indentation width. Returns position after it.
+    pub fn insert_tab(&mut self, at: &Position, tab_width: usize) -> Position {
+        match self.indentation_style {
+            Indentation::Tabs => self.insert(at, '\t'),
+            Indentation::Spaces(width) => {
+                let column = self.render_x(at, tab_width);
+                let spaces = width.saturating_sub(column.checked_rem(width).unwrap_or_default());
+                let mut position = at.clone();
+                for _ in 0..spaces {
+                    position = self.insert(&position, ' ');
+                }
+                position
+            }
+        }
+    }
+
+    /// Insert `c` as it is without indentation rules, e.g. when it's pasted
+    pub fn insert_pasted(&mut self, at: &Position, c: char) -> Position {
+        self.insert_char(at, c, false)
+    }
+
+    fn insert_char(&mut self, at: &Position, c: char, auto_indent: bool) -> Position {
         self.dirty = true;
+        self.revision = self.revision.wrapping_add(1);
 
-        if c == '\n' {
-            self.insert_newline(at);
+        let position = if c == '\n' && auto_indent {
+            self.insert_newline(at)
+        } else if c == '\n' {
+            self.split_row(at)
         } else if self.is_empty() {
             // Insert char to new line
             let mut row = Row::default();
             row.insert(0, c);
             self.rows.push(row);
+            Position { x: 1, y: 0 }
         } else {
+            let indentation = self.indentation(at.y);
+            let dedent = auto_indent
+                && self.file_type.indentation_rules().dedents_on(c)
+                && at.x > 0
+                && at.x == indentation.graphemes(true).count();
+            let mut x = at.x;
+            if dedent {
+                let dedented = self
+                    .opener_indentation(at.y)
+                    .unwrap_or_else(|| self.dedent(&indentation));
+                x = dedented.graphemes(true).count();
+                let rest: String = self
+                    .row(at.y)
+                    .map_or_else(String::new, Row::get_string)
+                    .graphemes(true)
+                    .skip(at.x)
+                    .collect();
+                if let Some(row) = self.rows.get_mut(at.y) {
+                    *row = Row::from(format!("{}{}", dedented, rest).as_str());
+                }
+            }
             // Insert char inside existing line
             #[allow(clippy::indexing_slicing)]
             let row = &mut self.rows[at.y];
-            row.insert(at.x, c);
-        }
+            let len = row.len();
+            row.insert(x, c);
+            // Cursor doesn't move when the char completes a grapheme, e.g. the second half of emoji flag 🇨🇿
+            Position {
+                x: x.saturating_add(row.len().saturating_sub(len)),
+                y: at.y,
+            }
+        };
         self.unhighlight_rows(at.y);
+        position
+    }
+
+    /// Highlight all rows again when they are drawn, e.g. after search matches were highlighted
+    pub fn reset_highlighting(&mut self) {
+        self.unhighlight_rows(0);
     }
 
     fn unhighlight_rows(&mut self, start: usize) {
@@ -111,6 +771,7 @@ impl Document {
         }
 
         self.dirty = true;
+        self.revision = self.revision.wrapping_add(1);
 
         let len = self.len();
         if at.x == self.row_len(at.y) && at.y + 1 < len {
@@ -124,13 +785,129 @@ impl Document {
         self.unhighlight_rows(at.y);
     }
 
+    /// Delete text between positions `start` and `end`
+    pub fn delete_range(&mut self, start: &Position, end: &Position) {
+        let last = self.len().saturating_sub(1);
+        let (start_y, end_y) = (start.y.min(last), end.y.min(last));
+        if self.is_empty() || (end_y, end.x) <= (start_y, start.x) {
+            return;
+        }
+        let rest = match self.rows.get_mut(end_y) {
+            Some(row) => row.split(end.x),
+            None => return,
+        };
+        self.rows.drain(start_y.saturating_add(1)..=end_y);
+        if let Some(row) = self.rows.get_mut(start_y) {
+            row.split(start.x);
+            row.append(&rest);
+        }
+        self.dirty = true;
+        self.revision = self.revision.wrapping_add(1);
+        self.unhighlight_rows(start_y);
+    }
+
+    /// Start of the word before position `at`, words may be on previous rows
+    pub fn previous_word_start(&self, at: &Position) -> Position {
+        let mut x = at.x;
+        for y in (0..=at.y.min(self.len().saturating_sub(1))).rev() {
+            if let Some(start) = self.row(y).and_then(|row| row.previous_word_start(x)) {
+                return Position { x: start, y };
+            }
+            x = usize::MAX;
+        }
+        Position::default()
+    }
+
+    /// End of the word after position `at`, words may be on next rows
+    pub fn next_word_end(&self, at: &Position) -> Position {
+        let mut x = at.x;
+        for y in at.y..self.len() {
+            if let Some(end) = self.row(y).and_then(|row| row.next_word_end(x)) {
+                return Position { x: end, y };
+            }
+            x = 0;
+        }
+        self.end()
+    }
+
+    /// Word at position `at` or just before it, empty when there's no word
+    pub fn word_at(&self, at: &Position) -> String {
+        let Position { x, y } = *at;
+        let row = if let Some(row) = self.row(y) {
+            row
+        } else {
+            return String::new();
+        };
+        for x in [x, x.saturating_sub(1)] {
+            let (start, end) = row.word_at(x);
+            let word = self.text(&Position { x: start, y }, &Position { x: end, y });
+            if word.chars().any(char::is_alphanumeric) {
+                return word;
+            }
+        }
+        String::new()
+    }
+
+    /// Blank row before the paragraph above row `y`
+    pub fn previous_paragraph(&self, y: usize) -> usize {
+        let is_blank = |y: &usize| self.row(*y).is_none_or(Row::is_blank);
+        (0..y)
+            .rev()
+            .skip_while(is_blank)
+            .find(is_blank)
+            .unwrap_or(0)
+    }
+
+    /// Blank row after the paragraph at or below row `y`
+    pub fn next_paragraph(&self, y: usize) -> usize {
+        let is_blank = |y: &usize| self.row(*y).is_none_or(Row::is_blank);
+        (y..self.len())
+            .skip_while(is_blank)
+            .find(is_blank)
+            .unwrap_or_else(|| self.len().saturating_sub(1))
+    }
+
+    /// Position after the last grapheme of the document
+    pub fn end(&self) -> Position {
+        let y = self.len().saturating_sub(1);
+        Position {
+            x: self.row_len(y),
+            y,
+        }
+    }
+
     /// # Errors
     ///
     /// Fails if file cannot be open in write mode or content cannot be written
     pub fn save(&mut self) -> io::Result<()> {
-        if let Some(ref file_name) = self.file_name {
-            let mut file = fs::File::create(file_name)?;
-            self.file_type = FileType::from(file_name);
+        if let Some(file_name) = self.file_name.clone() {
+            self.write(&file_name)?;
+        }
+        Ok(())
+    }
+
+    /// Save document to `file_name`, which becomes its file name only when it was written.
+    /// File type is detected again when the name changes.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file cannot be written, the document keeps its old name then
+    pub fn save_as(&mut self, file_name: &str) -> io::Result<()> {
+        self.write(file_name)?;
+        if self.file_name.as_deref() != Some(file_name) {
+            self.file_name = Some(file_name.to_owned());
+            if !self.is_hex() {
+                self.set_file_type(FileType::from(file_name));
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, file_name: &str) -> io::Result<()> {
+        let mut file = fs::File::create(file_name)?;
+        if let Some(ref bytes) = self.bytes {
+            file.write_all(bytes)?;
+        } else {
             let document_len = self.len();
             for (i, row) in self.rows.iter_mut().enumerate() {
                 file.write_all(row.as_bytes())?;
@@ -139,8 +916,10 @@ impl Document {
                     file.write_all(b"\n")?;
                 }
             }
-            self.dirty = false;
         }
+        self.dirty = false;
+        self.disk_state = DiskState::of(file_name);
+        self.seen_disk_state = self.disk_state;
         Ok(())
     }
 
@@ -148,8 +927,126 @@ impl Document {
         self.dirty
     }
 
+    /// Text between positions `start` and `end`, rows are separated by new line
+    pub fn text(&self, start: &Position, end: &Position) -> String {
+        let mut text = String::new();
+        for (y, row) in self
+            .rows
+            .iter()
+            .enumerate()
+            .skip(start.y)
+            .take(end.y.saturating_sub(start.y).saturating_add(1))
+        {
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { row.len() };
+            text.extend(
+                row.get_string()
+                    .graphemes(true)
+                    .skip(from)
+                    .take(to.saturating_sub(from)),
+            );
+            if y != end.y {
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// Text drawn in screen columns from `left` to `right` of rows from `top` to `bottom` inclusive,
+    /// one line per row. Tabs are expanded and short rows padded with spaces, so that the block
+    /// stays rectangular wherever it's pasted.
+    pub fn block_text(
+        &self,
+        top: usize,
+        bottom: usize,
+        left: usize,
+        right: usize,
+        tab_width: usize,
+    ) -> Vec<String> {
+        (top..=bottom)
+            .map(|y| {
+                let mut text = self.rows.get(y).map_or_else(String::new, |row| {
+                    let (_, inside, _) = row.split_columns(left, right, tab_width);
+                    inside
+                });
+                let width = Row::from(text.as_str()).width_until(usize::MAX, tab_width);
+                text.push_str(&" ".repeat(right.saturating_sub(left).saturating_sub(width)));
+                text
+            })
+            .collect()
+    }
+
+    /// Replace screen columns from `left` to `right` of rows from `top` by `lines`, one line per row.
+    /// Rows shorter than `left` are padded with spaces, rows are added after the end of document
+    /// when there are more lines.
+    pub fn replace_block(
+        &mut self,
+        top: usize,
+        left: usize,
+        right: usize,
+        lines: &[String],
+        tab_width: usize,
+    ) {
+        for (y, line) in (top..).zip(lines) {
+            if y >= self.len() {
+                if line.is_empty() {
+                    break;
+                }
+                self.rows.push(Row::default());
+            }
+            if let Some(row) = self.rows.get_mut(y) {
+                let (mut text, _, after) = row.split_columns(left, right, tab_width);
+                if !line.is_empty() {
+                    let width = Row::from(text.as_str()).width_until(usize::MAX, tab_width);
+                    text.push_str(&" ".repeat(left.saturating_sub(width)));
+                }
+                text.push_str(line);
+                text.push_str(&after);
+                *row = Row::from(text.as_str());
+            }
+        }
+        self.dirty = true;
+        self.revision = self.revision.wrapping_add(1);
+        self.unhighlight_rows(top);
+    }
+
+    /// Replace `count` rows starting at row `start` by `lines`
+    pub fn replace_rows(&mut self, start: usize, count: usize, lines: &[String]) {
+        let end = start.saturating_add(count).min(self.len());
+        let start = start.min(end);
+        self.rows.splice(
+            start..end,
+            lines.iter().map(|line| Row::from(line.as_str())),
+        );
+        self.dirty = true;
+        self.revision = self.revision.wrapping_add(1);
+        self.unhighlight_rows(start);
+    }
+
+    /// Sort rows from `start` to `end` inclusive. Empty last row, which ends file with newline, stays last.
+    pub fn sort_rows(&mut self, start: usize, end: usize) {
+        let trailing = usize::from(self.rows.last().is_some_and(Row::is_empty));
+        let end = end.min(self.len().saturating_sub(trailing).saturating_sub(1));
+        if let Some(rows) = self.rows.get_mut(start..=end) {
+            if rows.len() > 1 {
+                rows.sort_by_cached_key(Row::get_string);
+                self.dirty = true;
+                self.revision = self.revision.wrapping_add(1);
+                self.unhighlight_rows(start);
+            }
+        }
+    }
+
+    /// Find next match of `query` from position `at`. With `wrap_around` option search continues
+    /// from the other end of document.
     #[allow(clippy::indexing_slicing)]
-    pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
+    pub fn find(
+        &self,
+        query: &str,
+        at: &Position,
+        direction: SearchDirection,
+        options: SearchOptions,
+    ) -> Option<Position> {
         let mut position = Position { x: at.x, y: at.y };
 
         let start = if direction == SearchDirection::Forward {
@@ -164,7 +1061,7 @@ impl Document {
         };
         for _ in start..end {
             if let Some(row) = self.rows.get(position.y) {
-                if let Some(x) = row.find(query, position.x, direction) {
+                if let Some(x) = row.find(query, position.x, direction, options) {
                     position.x = x;
                     return Some(position);
                 }
@@ -179,9 +1076,41 @@ impl Document {
                 return None;
             }
         }
+        if options.wrap_around {
+            let from = if direction == SearchDirection::Forward {
+                Position::default()
+            } else {
+                self.end()
+            };
+            let options = SearchOptions {
+                wrap_around: false,
+                ..options
+            };
+            return self.find(query, &from, direction, options);
+        }
         None
     }
-    pub fn highlight(&mut self, word: &Option<String>, until: Option<usize>) {
+
+    /// Positions of all matches of `query` in document
+    pub fn find_all(&self, query: &str, options: SearchOptions) -> Vec<Position> {
+        let query_len = query.graphemes(true).count();
+        let mut matches = Vec::new();
+        for (y, row) in self.rows.iter().enumerate() {
+            let mut x = 0;
+            while let Some(found) = row.find(query, x, SearchDirection::Forward, options) {
+                matches.push(Position { x: found, y });
+                x = found.saturating_add(query_len.max(1));
+            }
+        }
+        matches
+    }
+
+    pub fn highlight(
+        &mut self,
+        word: &Option<String>,
+        search_options: SearchOptions,
+        until: Option<usize>,
+    ) {
         let mut start_with_comment = false;
         let until = if let Some(until) = until {
             if until.saturating_add(1) < self.rows.len() {
@@ -197,8 +1126,602 @@ impl Document {
             start_with_comment = row.highlight(
                 self.file_type.highlighting_options(),
                 word,
+                search_options,
                 start_with_comment,
             );
         }
     }
+
+    /// Complete merge conflicts in document, in order of rows. They are found by `update_conflicts`.
+    pub fn conflicts(&self) -> &[Conflict] {
+        self.conflicts
+            .as_ref()
+            .map_or(&[], |(_, conflicts)| conflicts.as_slice())
+    }
+
+    /// Highlighting of row `y` if it's part of a merge conflict
+    pub fn conflict_highlighting(&self, y: usize) -> Option<highlighting::Type> {
+        self.conflicts()
+            .iter()
+            .find(|conflict| conflict.contains(y))
+            .and_then(|conflict| conflict.section(y))
+    }
+
+    /// Find merge conflicts again if content changed since they were found
+    pub fn update_conflicts(&mut self) {
+        if !matches!(self.conflicts, Some((revision, _)) if revision == self.revision) {
+            self.conflicts = Some((self.revision, self.find_conflicts()));
+        }
+    }
+
+    fn find_conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        let mut current: Option<Conflict> = None;
+        for (y, row) in self.rows.iter().enumerate() {
+            let bytes = row.as_bytes();
+            if bytes.starts_with(b"<<<<<<<") {
+                current = Some(Conflict {
+                    start: y,
+                    base: None,
+                    separator: y,
+                    end: y,
+                });
+            } else if let Some(ref mut conflict) = current {
+                if bytes.starts_with(b"|||||||") && conflict.separator == conflict.start {
+                    conflict.base = Some(y);
+                } else if bytes.starts_with(b"=======") && conflict.separator == conflict.start {
+                    conflict.separator = y;
+                } else if bytes.starts_with(b">>>>>>>") && conflict.separator != conflict.start {
+                    conflict.end = y;
+                    conflicts.extend(current.take());
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Replace merge conflict by sections chosen by `resolution`
+    pub fn resolve_conflict(&mut self, conflict: &Conflict, resolution: Resolution) {
+        let ours = conflict.start.saturating_add(1)..conflict.base.unwrap_or(conflict.separator);
+        let theirs = conflict.separator.saturating_add(1)..conflict.end;
+        let lines = self.lines();
+        let section = |range: std::ops::Range<usize>| lines.get(range).unwrap_or_default().to_vec();
+        let kept = match resolution {
+            Resolution::Ours => section(ours),
+            Resolution::Theirs => section(theirs),
+            Resolution::Both => {
+                let mut both = section(ours);
+                both.extend(section(theirs));
+                both
+            }
+        };
+        self.replace_rows(
+            conflict.start,
+            conflict
+                .end
+                .saturating_sub(conflict.start)
+                .saturating_add(1),
+            &kept,
+        );
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.bytes.is_some()
+    }
+
+    /// Number of bytes of document opened in hex view
+    pub fn hex_len(&self) -> usize {
+        self.bytes.as_ref().map_or(0, Vec::len)
+    }
+
+    pub fn byte_offset(at: &Position) -> usize {
+        at.y.saturating_mul(HEX_BYTES_PER_ROW).saturating_add(at.x)
+    }
+
+    pub fn hex_position(offset: usize) -> Position {
+        Position {
+            x: offset.checked_rem(HEX_BYTES_PER_ROW).unwrap_or(0),
+            y: offset.checked_div(HEX_BYTES_PER_ROW).unwrap_or(0),
+        }
+    }
+
+    pub fn hex_byte(&self, at: &Position) -> Option<u8> {
+        self.bytes
+            .as_ref()
+            .and_then(|bytes| bytes.get(Self::byte_offset(at)).copied())
+    }
+
+    /// Overwrite byte at position `at`. Writing right after the last byte appends a new one.
+    pub fn hex_set_byte(&mut self, at: &Position, byte: u8) {
+        let offset = Self::byte_offset(at);
+        if let Some(ref mut bytes) = self.bytes {
+            if let Some(old) = bytes.get_mut(offset) {
+                *old = byte;
+            } else if offset == bytes.len() {
+                bytes.push(byte);
+            } else {
+                return;
+            }
+            self.dirty = true;
+            self.revision = self.revision.wrapping_add(1);
+            self.render_hex_rows(at.y);
+        }
+    }
+
+    pub fn hex_insert_byte(&mut self, at: &Position, byte: u8) {
+        let offset = Self::byte_offset(at);
+        if let Some(ref mut bytes) = self.bytes {
+            if offset > bytes.len() {
+                return;
+            }
+            bytes.insert(offset, byte);
+            self.dirty = true;
+            self.revision = self.revision.wrapping_add(1);
+            self.render_hex_rows(at.y);
+        }
+    }
+
+    pub fn hex_delete_byte(&mut self, at: &Position) {
+        let offset = Self::byte_offset(at);
+        if let Some(ref mut bytes) = self.bytes {
+            if offset >= bytes.len() {
+                return;
+            }
+            bytes.remove(offset);
+            self.dirty = true;
+            self.revision = self.revision.wrapping_add(1);
+            self.render_hex_rows(at.y);
+        }
+    }
+
+    /// Find sequence of bytes `pattern`. Forward search starts at position `at`,
+    /// backward search looks for the last match starting before `at`.
+    pub fn hex_find(
+        &self,
+        pattern: &[u8],
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<Position> {
+        let bytes = self.bytes.as_ref()?;
+        if pattern.is_empty() {
+            return None;
+        }
+        let offset = Self::byte_offset(at);
+        let windows = bytes.windows(pattern.len()).enumerate();
+        let found = if direction == SearchDirection::Forward {
+            windows.skip(offset).find(|&(_, window)| window == pattern)
+        } else {
+            windows.take(offset).rfind(|&(_, window)| window == pattern)
+        };
+        found.map(|(index, _)| Self::hex_position(index))
+    }
+
+    /// Render bytes as rows of hex view, starting with row `from` until the end of document
+    fn render_hex_rows(&mut self, from: usize) {
+        if let Some(ref bytes) = self.bytes {
+            self.rows.truncate(from);
+            for (index, chunk) in bytes.chunks(HEX_BYTES_PER_ROW).enumerate().skip(from) {
+                self.rows.push(Row::from(
+                    render_hex_row(index.saturating_mul(HEX_BYTES_PER_ROW), chunk).as_str(),
+                ));
+            }
+            // Last row is kept for appending of bytes, even if it's empty
+            if bytes.len().checked_rem(HEX_BYTES_PER_ROW) == Some(0) {
+                self.rows
+                    .push(Row::from(render_hex_row(bytes.len(), &[]).as_str()));
+            }
+        }
+    }
+}
+
+/// Render one row of hex view, e.g. `00000010  48 65 6c 6c 6f 0a  |Hello.|`
+fn render_hex_row(offset: usize, chunk: &[u8]) -> String {
+    let mut hex = String::new();
+    let mut ascii = String::new();
+    for byte in chunk {
+        hex.push_str(&format!("{:02x} ", byte));
+        ascii.push(if byte.is_ascii_graphic() || *byte == b' ' {
+            *byte as char
+        } else {
+            '.'
+        });
+    }
+    format!(
+        "{:08x}  {:width$} |{}|",
+        offset,
+        hex,
+        ascii,
+        width = HEX_BYTES_PER_ROW.saturating_mul(3)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, path::PathBuf, process};
+
+    fn document(lines: &[&str]) -> Document {
+        Document {
+            rows: lines.iter().map(|&line| Row::from(line)).collect(),
+            ..Document::default()
+        }
+    }
+
+    /// File `name` with `contents` in temporary directory, and its name as a string
+    fn temp_file(name: &str, contents: &[u8]) -> (PathBuf, String) {
+        let path = env::temp_dir().join(format!("hecto-{}-{}", process::id(), name));
+        fs::write(&path, contents).unwrap();
+        let file_name = path.to_string_lossy().into_owned();
+        (path, file_name)
+    }
+
+    const CONFLICT: [&str; 9] = [
+        "before",
+        "<<<<<<< HEAD",
+        "ours",
+        "||||||| base",
+        "base",
+        "=======",
+        "theirs",
+        ">>>>>>> branch",
+        "after",
+    ];
+
+    #[test]
+    fn finds_conflicts() {
+        let mut document = document(&CONFLICT);
+        document.update_conflicts();
+        let conflicts = document.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(
+            (
+                conflict.start,
+                conflict.base,
+                conflict.separator,
+                conflict.end
+            ),
+            (1, Some(3), 5, 7)
+        );
+        assert!(document.conflict_highlighting(0).is_none());
+        assert!(document.conflict_highlighting(1) == Some(highlighting::Type::ConflictMarker));
+        assert!(document.conflict_highlighting(2) == Some(highlighting::Type::ConflictOurs));
+        assert!(document.conflict_highlighting(4) == Some(highlighting::Type::ConflictBase));
+        assert!(document.conflict_highlighting(6) == Some(highlighting::Type::ConflictTheirs));
+        assert!(document.conflict_highlighting(8).is_none());
+    }
+
+    #[test]
+    fn ignores_incomplete_conflicts() {
+        for lines in [
+            ["<<<<<<< HEAD", "ours", "=======", "theirs"].as_slice(),
+            ["<<<<<<< HEAD", "ours", ">>>>>>> branch"].as_slice(),
+        ] {
+            let mut document = document(lines);
+            document.update_conflicts();
+            assert!(document.conflicts().is_empty());
+        }
+    }
+
+    #[test]
+    fn updates_conflicts_after_change() {
+        let mut document = document(&CONFLICT);
+        document.update_conflicts();
+        document.delete_range(&Position { x: 0, y: 7 }, &Position { x: 0, y: 8 });
+        assert_eq!(document.conflicts().len(), 1);
+        document.update_conflicts();
+        assert!(document.conflicts().is_empty());
+    }
+
+    #[test]
+    fn resolves_conflicts() {
+        let resolved = |resolution| {
+            let mut document = document(&CONFLICT);
+            document.update_conflicts();
+            let conflict = document.conflicts()[0].clone();
+            document.resolve_conflict(&conflict, resolution);
+            document.lines()
+        };
+        assert_eq!(resolved(Resolution::Ours), ["before", "ours", "after"]);
+        assert_eq!(resolved(Resolution::Theirs), ["before", "theirs", "after"]);
+        assert_eq!(
+            resolved(Resolution::Both),
+            ["before", "ours", "theirs", "after"]
+        );
+    }
+
+    fn detected(lines: &[&str]) -> Option<Indentation> {
+        Indentation::detect(&document(lines).rows)
+    }
+
+    #[test]
+    fn detects_indentation() {
+        assert!(detected(&["fn main() {", "\tlet a = 1;", "}"]) == Some(Indentation::Tabs));
+        assert!(detected(&["a {", "  b {", "    c", "  }", "}"]) == Some(Indentation::Spaces(2)));
+        assert!(
+            detected(&["a {", "    b {", "        c", "    }", "}"])
+                == Some(Indentation::Spaces(4))
+        );
+        assert!(detected(&["a", "", "b"]).is_none());
+    }
+
+    #[test]
+    fn ignores_alignment_when_detecting_indentation() {
+        let lines = [
+            "/*",
+            " * comment",
+            " */",
+            "a {",
+            "    b",
+            "    /*",
+            "     * comment",
+            "     */",
+            "}",
+        ];
+        assert!(detected(&lines) == Some(Indentation::Spaces(4)));
+    }
+
+    #[test]
+    fn converts_indentation() {
+        let mut document = document(&["a {", "    b {", "      c", "    }", "}"]);
+        document.indentation_style = Indentation::Spaces(4);
+        document.convert_indentation(Indentation::Tabs, 8);
+        assert_eq!(document.lines(), ["a {", "\tb {", "\t  c", "\t}", "}"]);
+        assert!(document.indentation_style() == Indentation::Tabs);
+        assert!(document.is_dirty());
+        document.convert_indentation(Indentation::Spaces(2), 8);
+        assert_eq!(document.lines(), ["a {", "  b {", "    c", "  }", "}"]);
+    }
+
+    #[test]
+    fn inserts_tab_in_indentation_style() {
+        let mut document = document(&["ab"]);
+        document.indentation_style = Indentation::Spaces(4);
+        let position = document.insert_tab(&Position { x: 1, y: 0 }, 8);
+        assert_eq!(document.lines(), ["a   b"]);
+        assert!(position == Position { x: 4, y: 0 });
+        document.indentation_style = Indentation::Tabs;
+        document.insert_tab(&Position { x: 0, y: 0 }, 8);
+        assert_eq!(document.lines(), ["\ta   b"]);
+    }
+
+    fn rust_document(lines: &[&str]) -> Document {
+        let mut document = document(lines);
+        document.file_type = FileType::from("main.rs");
+        document.indentation_style = Indentation::Spaces(4);
+        document
+    }
+
+    #[test]
+    fn indents_after_opening_bracket() {
+        let mut document = rust_document(&["fn x() {"]);
+        let position = document.insert(&Position { x: 8, y: 0 }, '\n');
+        assert_eq!(document.lines(), ["fn x() {", "    "]);
+        assert!(position == Position { x: 4, y: 1 });
+    }
+
+    #[test]
+    fn moves_closing_bracket_to_its_own_line() {
+        let mut document = rust_document(&["    x = {}"]);
+        let position = document.insert(&Position { x: 9, y: 0 }, '\n');
+        assert_eq!(document.lines(), ["    x = {", "        ", "    }"]);
+        assert!(position == Position { x: 8, y: 1 });
+    }
+
+    #[test]
+    fn keeps_indentation_of_plain_text() {
+        let mut document = document(&["  a {"]);
+        let position = document.insert(&Position { x: 5, y: 0 }, '\n');
+        assert_eq!(document.lines(), ["  a {", "  "]);
+        assert!(position == Position { x: 2, y: 1 });
+    }
+
+    #[test]
+    fn dedents_closing_bracket() {
+        let mut document = rust_document(&["    if x {", "        a", "        "]);
+        let position = document.insert(&Position { x: 8, y: 2 }, '}');
+        assert_eq!(document.lines(), ["    if x {", "        a", "    }"]);
+        assert!(position == Position { x: 5, y: 2 });
+        // Closing bracket after text isn't moved
+        let position = document.insert(&Position { x: 9, y: 1 }, ')');
+        assert_eq!(document.lines()[1], "        a)");
+        assert!(position == Position { x: 10, y: 1 });
+    }
+
+    #[test]
+    fn pastes_without_indentation_rules() {
+        let mut document = rust_document(&["fn x() {", "        "]);
+        document.insert_pasted(&Position { x: 8, y: 1 }, '}');
+        document.insert_pasted(&Position { x: 8, y: 0 }, '\n');
+        assert_eq!(document.lines(), ["fn x() {", "", "        }"]);
+    }
+
+    #[test]
+    fn sorts_rows_before_trailing_newline() {
+        let (path, file_name) = temp_file("sort", b"b\na\n");
+        let mut document = Document::open(&file_name).unwrap();
+        document.sort_rows(0, document.len());
+        document.save().unwrap();
+        let sorted = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(sorted, "a\nb\n");
+    }
+
+    #[test]
+    fn sorts_selected_rows() {
+        let mut document = document(&["d", "c", "b", "a"]);
+        document.sort_rows(1, 2);
+        assert_eq!(document.lines(), ["d", "b", "c", "a"]);
+        assert!(document.is_dirty());
+    }
+
+    #[test]
+    fn deletes_range() {
+        let mut document = document(&["one two", "three", "four five"]);
+        document.delete_range(&Position { x: 4, y: 0 }, &Position { x: 5, y: 2 });
+        assert_eq!(document.lines(), ["one five"]);
+        document.delete_range(&Position { x: 1, y: 0 }, &Position { x: 3, y: 0 });
+        assert_eq!(document.lines(), ["o five"]);
+        assert_eq!(document.revision(), 2);
+        document.delete_range(&Position { x: 3, y: 0 }, &Position { x: 1, y: 0 });
+        assert_eq!(document.lines(), ["o five"]);
+        assert_eq!(document.revision(), 2);
+    }
+
+    #[test]
+    fn keeps_file_type_on_save() {
+        let (path, file_name) = temp_file("type.txt", b"fn main() {}");
+        let mut document = Document::open(&file_name).unwrap();
+        document.set_file_type(FileType::from_name("rust").unwrap());
+        document.save().unwrap();
+        assert_eq!(document.file_type(), "Rust");
+        document.save_as(&file_name).unwrap();
+        assert_eq!(document.file_type(), "Rust");
+        let renamed = path.with_extension("diff");
+        let renamed_name = renamed.to_string_lossy().into_owned();
+        document.save_as(&renamed_name).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&renamed).unwrap();
+        assert_eq!(document.file_type(), "Diff");
+    }
+
+    #[test]
+    fn refuses_binary_file_as_text() {
+        let (path, file_name) = temp_file("binary", b"\x7fELF\0\x01");
+        let text = Document::open(&file_name);
+        let hex = Document::open_hex(&file_name);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            text.err().map(|error| error.kind()),
+            Some(io::ErrorKind::InvalidData)
+        );
+        assert_eq!(hex.map(|document| document.hex_len()).ok(), Some(6));
+    }
+
+    #[test]
+    fn renders_hex_rows() {
+        let (path, file_name) = temp_file("rows", b"0123456789abcdef\n");
+        let document = Document::open_hex(&file_name).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            document.lines(),
+            [
+                "00000000  30 31 32 33 34 35 36 37 38 39 61 62 63 64 65 66  |0123456789abcdef|",
+                "00000010  0a                                               |.|",
+            ]
+        );
+        assert!(document.hex_byte(&Document::hex_position(16)) == Some(b'\n'));
+        assert_eq!(Document::byte_offset(&Position { x: 2, y: 1 }), 18);
+    }
+
+    #[test]
+    fn edits_bytes() {
+        let (path, file_name) = temp_file("edit", b"abc");
+        let mut document = Document::open_hex(&file_name).unwrap();
+        document.hex_set_byte(&Position { x: 0, y: 0 }, b'x');
+        document.hex_set_byte(&Position { x: 3, y: 0 }, b'd');
+        document.hex_set_byte(&Position { x: 5, y: 0 }, b'f');
+        document.hex_insert_byte(&Position { x: 1, y: 0 }, b'-');
+        document.hex_delete_byte(&Position { x: 3, y: 0 });
+        assert!(document.is_dirty());
+        document.save().unwrap();
+        let saved = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved, b"x-bd");
+    }
+
+    #[test]
+    fn finds_bytes() {
+        let (path, file_name) = temp_file("find", b"abcabc");
+        let document = Document::open_hex(&file_name).unwrap();
+        fs::remove_file(&path).unwrap();
+        let find = |x, direction| {
+            document
+                .hex_find(b"bc", &Position { x, y: 0 }, direction)
+                .map(|position| position.x)
+        };
+        assert_eq!(find(0, SearchDirection::Forward), Some(1));
+        assert_eq!(find(2, SearchDirection::Forward), Some(4));
+        assert_eq!(find(5, SearchDirection::Forward), None);
+        assert_eq!(find(4, SearchDirection::Backward), Some(1));
+        assert_eq!(find(1, SearchDirection::Backward), None);
+    }
+
+    #[test]
+    fn finds_position_at_screen_column() {
+        let document = document(&["\tx", "\u{4e2d}\u{6587}"]);
+        assert!(document.position_at(0, 4, 4) == Position { x: 1, y: 0 });
+        assert!(document.position_at(0, 5, 4) == Position { x: 2, y: 0 });
+        assert!(document.position_at(0, 2, 4) == Position { x: 0, y: 0 });
+        assert!(document.position_at(1, 3, 4) == Position { x: 1, y: 1 });
+        assert!(document.position_at(9, 9, 4) == Position { x: 2, y: 1 });
+    }
+
+    #[test]
+    fn finds_with_wrap_around() {
+        let document = document(&["one", "two", "one"]);
+        let mut options = SearchOptions::default();
+        let at = Position { x: 1, y: 2 };
+        assert!(document
+            .find("one", &at, SearchDirection::Forward, options)
+            .is_none());
+        let found = document.find("two", &at, SearchDirection::Backward, options);
+        assert!(found == Some(Position { x: 0, y: 1 }));
+        options.wrap_around = true;
+        let found = document.find("one", &at, SearchDirection::Forward, options);
+        assert!(found == Some(Position { x: 0, y: 0 }));
+        let found = document.find(
+            "one",
+            &Position { x: 0, y: 0 },
+            SearchDirection::Backward,
+            options,
+        );
+        assert!(found == Some(Position { x: 0, y: 2 }));
+    }
+
+    #[test]
+    fn finds_word_at_position() {
+        let document = document(&["let answer = 42;", ""]);
+        let word = |x, y| document.word_at(&Position { x, y });
+        assert_eq!(word(5, 0), "answer");
+        assert_eq!(word(10, 0), "answer");
+        assert_eq!(word(11, 0), "");
+        assert_eq!(word(15, 0), "42");
+        assert_eq!(word(0, 1), "");
+        assert_eq!(word(0, 5), "");
+    }
+
+    #[test]
+    fn renames_on_successful_save_as() {
+        let mut document = document(&["text"]);
+        let missing = env::temp_dir().join(format!("hecto-missing-{}", process::id()));
+        let failing = missing.join("file.rs").to_string_lossy().into_owned();
+        assert!(document.save_as(&failing).is_err());
+        assert!(document.file_name.is_none());
+
+        let (path, file_name) = temp_file("renamed.rs", b"");
+        document.save_as(&file_name).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved, "text");
+        assert_eq!(document.file_name, Some(file_name));
+        assert_eq!(document.file_type(), "Rust");
+        assert!(!document.is_dirty());
+    }
+
+    #[test]
+    fn points_to_locations() {
+        let mut document = Document::location_list("Search results");
+        document.push_location("a.txt", None);
+        document.push_location(
+            "1: needle",
+            Some(("a.txt".to_owned(), Position { x: 0, y: 0 })),
+        );
+        assert!(document.is_location_list());
+        assert!(document.is_read_only());
+        assert_eq!(document.lines(), ["a.txt", "1: needle"]);
+        assert!(document.location(0).is_none());
+        assert!(document.location(1) == Some(&("a.txt".to_owned(), Position { x: 0, y: 0 })));
+        assert!(document.location(2).is_none());
+    }
 }
@@ -1,20 +1,76 @@
+use crate::block::{self, Block};
+use crate::browser;
+use crate::command;
+use crate::compare::Compare;
+use crate::cursors::{self, Cursors};
+use crate::diff;
+use crate::document::{Indentation, Resolution};
+use crate::finder::Finder;
+use crate::git::{self, BlameLine, Marker};
+use crate::grep::Grep;
+use crate::location::{self, Line as LocationLine, Location};
+use crate::macros::{self, Macros, Recording, Replay, MAX_MACRO_RUNS};
+use crate::prompt::{self, Line};
+use crate::row::SELECTION_BG_COLOR;
+use crate::terminal::{CTRL_BACKSPACE, CTRL_DELETE};
+use crate::timer::{TimerId, Timers};
 use crate::Document;
+use crate::FileType;
 use crate::Row;
 use crate::Terminal;
 
 use std::cmp;
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::time::Instant;
 use termion::color;
-use termion::event::Key;
+use termion::event::{Event, Key, MouseButton, MouseEvent};
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
 const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
+const GUTTER_FG_COLOR: color::Rgb = color::Rgb(128, 128, 128);
+const ADDED_COLOR: color::Rgb = color::Rgb(80, 170, 80);
+const MODIFIED_COLOR: color::Rgb = color::Rgb(200, 160, 40);
+const DELETED_COLOR: color::Rgb = color::Rgb(210, 70, 70);
+const POPUP_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
+const POPUP_BG_COLOR: color::Rgb = color::Rgb(255, 250, 205);
+const EXTRA_CURSOR_BG_COLOR: color::Rgb = color::Rgb(160, 160, 160);
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const QUIT_TIMES: u8 = 3;
+const HEX_HELP: &str =
+    "Binary file opened in hex view. Insert = toggle insert/overwrite | Ctrl-F = find bytes";
+const COMPARE_HELP: &str =
+    "DIFF: Alt-O = other side | Alt-./Alt-, = next/previous hunk | put-hunk, get-hunk = copy hunk | close-compare";
+const BROWSER_HELP: &str =
+    "Enter = open | - = parent | n = new (dir/ for directory) | r = rename | d = delete | g = refresh";
+const BLOCK_HELP: &str =
+    "BLOCK: move to resize | type = insert on every line | Ctrl-C = copy | Ctrl-X = cut | Esc = cancel";
+/// How long a status message stays visible
+const STATUS_MESSAGE_DURATION: Duration = Duration::from_secs(5);
+/// How long typing has to pause before blame of the edited document is updated
+const BLAME_UPDATE_DELAY: Duration = Duration::from_millis(500);
+/// How often the open file is checked for changes made by other programs
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Maximum delay between two clicks to be taken as a double-click
+const DOUBLE_CLICK_DURATION: Duration = Duration::from_millis(400);
+/// Number of lines scrolled by one step of mouse wheel
+const WHEEL_SCROLL_LINES: usize = 3;
+const DEFAULT_TAB_WIDTH: usize = 4;
+/// Width of blame annotation left of rows, e.g. `1a2b3c4d Jane Doe   2021-11-05 `
+const BLAME_WIDTH: usize = 31;
+/// Maximum number of command line suggestions shown at once
+const COMMAND_SUGGESTIONS: usize = 5;
+/// Maximum number of rows taken by the file finder
+const FINDER_HEIGHT: u16 = 12;
+/// How long project search runs at once before the editor handles input again
+const GREP_TIME_SLICE: Duration = Duration::from_millis(20);
 
 #[non_exhaustive]
 #[derive(PartialEq, Clone, Copy)]
@@ -23,7 +79,50 @@ pub enum SearchDirection {
     Backward,
 }
 
-#[derive(Default, Clone)]
+/// Flags toggled from search prompt
+#[derive(Default, Clone, Copy)]
+#[non_exhaustive]
+pub struct SearchOptions {
+    pub ignore_case: bool,
+    /// Ignore case unless query contains an uppercase letter, overrides `ignore_case`
+    pub smart_case: bool,
+    /// Match only whole words according to Unicode word boundaries
+    pub whole_word: bool,
+    /// Continue from the other end of document when there are no more matches
+    pub wrap_around: bool,
+}
+
+impl SearchOptions {
+    /// Whether case is ignored when searching for `query`
+    pub fn ignores_case(self, query: &str) -> bool {
+        if self.smart_case {
+            !query.chars().any(char::is_uppercase)
+        } else {
+            self.ignore_case
+        }
+    }
+
+    /// Enabled flags, e.g. `(ignore case, wrap)`
+    fn describe(self) -> String {
+        let flags: Vec<&str> = [
+            (self.ignore_case, "ignore case"),
+            (self.smart_case, "smart case"),
+            (self.whole_word, "whole word"),
+            (self.wrap_around, "wrap"),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, name)| *name)
+        .collect();
+        if flags.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", flags.join(", "))
+        }
+    }
+}
+
+#[derive(Default, Clone, PartialEq)]
 #[non_exhaustive]
 pub struct Position {
     pub x: usize,
@@ -44,6 +143,13 @@ impl StatusMessage {
     }
 }
 
+/// Document with its own cursor and scrolling, kept aside while another document is shown
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+}
+
 pub struct Editor {
     should_quit: bool,
     terminal: Terminal,
@@ -53,241 +159,2670 @@ pub struct Editor {
     status_message: StatusMessage,
     quit_times: u8,
     highlighted_word: Option<String>,
+    /// In hex view the low nibble of byte under cursor is edited next
+    hex_nibble: bool,
+    /// In hex view typed bytes are inserted instead of overwritten
+    hex_insert: bool,
+    /// Buffers covered by the current one, e.g. a file under its diff. The last one is shown when current is closed.
+    hidden_buffers: Vec<Buffer>,
+    /// Periodic tasks run while waiting for input
+    timers: Timers<Self>,
+    /// A question or prompt is waiting for an answer in the message bar
+    prompt_active: bool,
+    /// Start of selection, which ends at the cursor
+    selection: Option<Position>,
+    /// Time and position of the last mouse click, for detection of double-click
+    last_click: Option<(Instant, Position)>,
+    /// Left mouse button is held and moving the mouse extends selection
+    dragging: bool,
+    tab_width: usize,
+    /// Text shown after the user's input in prompt, e.g. completion suggestions
+    prompt_hint: Option<String>,
+    search_options: SearchOptions,
+    /// Answers of each prompt, the last one is the most recent
+    prompt_history: HashMap<String, Vec<String>>,
+    /// Screen column of cursor in message bar while a prompt is active
+    prompt_cursor: Option<usize>,
+    /// Text removed by the last kill command in a prompt
+    prompt_killed: String,
+    /// File finder shown while its prompt is active
+    finder: Option<Finder>,
+    /// Project search filling the current location list, and its timer
+    grep: Option<(Grep, TimerId)>,
+    /// Pending update of blame after the document changed
+    blame_timer: Option<TimerId>,
+    /// Lines shown in a box under the cursor until the next key is pressed
+    popup: Option<Vec<String>>,
+    /// Side-by-side diff of the current document with another file
+    compare: Option<Compare>,
+    macros: Macros,
+    recording: Option<Recording>,
+    replay: Replay,
+    cursors: Cursors,
+    /// Corner of rectangular block selection opposite to the cursor
+    block_anchor: Option<Position>,
+    /// Lines of the last copied or cut block
+    block_clipboard: Vec<String>,
 }
 
 impl Editor {
     pub fn run(&mut self) {
+        self.timers
+            .register(DISK_CHECK_INTERVAL, Self::check_disk_change);
         loop {
             if let Err(error) = self.refresh_screen() {
                 die(&error);
             }
-            if self.should_quit {
+            if self.should_quit {
+                break;
+            }
+            if let Err(error) = self.process_keypress() {
+                die(&error);
+            }
+        }
+    }
+
+    pub fn default() -> Self {
+        let args: Vec<String> = env::args().skip(1).collect();
+        let compared = match args.as_slice() {
+            [flag, left, right] if flag == "--diff" => Some((left.as_str(), right.as_str())),
+            _ => None,
+        };
+        let diff_usage = compared.is_none() && args.iter().any(|arg| arg == "--diff");
+        let (file_name, location) = if let Some((left, _)) = compared {
+            (Some(left), None)
+        } else if diff_usage {
+            (None, None)
+        } else {
+            parse_arguments(&args)
+        };
+        let mut initial_status = if diff_usage {
+            String::from("ERR: Usage: --diff <left file> <right file>")
+        } else {
+            String::from("HELP: Ctrl-F = find | Ctrl-S = save | Ctrl-O = open | Ctrl-E = command | Ctrl-Q = quit")
+        };
+        let document = if let Some(file_name) = file_name {
+            if let Ok(doc) = open_document(file_name) {
+                if let Some(help) = document_help(&doc) {
+                    help.clone_into(&mut initial_status);
+                }
+                doc
+            } else {
+                initial_status = format!("ERR: Could not open file: {}", file_name);
+                Document::default()
+            }
+        } else {
+            Document::default()
+        };
+
+        #[allow(clippy::expect_used)]
+        let mut editor = Self {
+            should_quit: false,
+            terminal: Terminal::default().expect("Failed to initialize terminal"),
+            cursor_position: Position::default(),
+            document,
+            offset: Position::default(),
+            status_message: StatusMessage::from(initial_status),
+            quit_times: QUIT_TIMES,
+            highlighted_word: None,
+            hex_nibble: false,
+            hex_insert: false,
+            hidden_buffers: Vec::new(),
+            timers: Timers::default(),
+            prompt_active: false,
+            selection: None,
+            last_click: None,
+            dragging: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+            prompt_hint: None,
+            search_options: SearchOptions::default(),
+            prompt_history: HashMap::new(),
+            prompt_cursor: None,
+            prompt_killed: String::new(),
+            finder: None,
+            grep: None,
+            blame_timer: None,
+            popup: None,
+            compare: None,
+            macros: Macros::load(),
+            recording: None,
+            replay: Replay::default(),
+            cursors: Cursors::default(),
+            block_anchor: None,
+            block_clipboard: Vec::new(),
+        };
+        if let Some(location) = location {
+            editor.go_to(&location);
+        }
+        if let Some((_, right)) = compared {
+            editor.compare_with(right);
+        }
+        editor
+    }
+
+    fn refresh_screen(&mut self) -> io::Result<()> {
+        Terminal::cursor_hide();
+        Terminal::set_cursor_position(&Position::default());
+        if self.should_quit {
+            Terminal::clear_screen();
+            println!("Goodbye.\r");
+        } else {
+            self.document.update_git_changes();
+            self.document.update_conflicts();
+            self.document.highlight(
+                &self.highlighted_word,
+                self.search_options,
+                Some(
+                    self.offset
+                        .y
+                        .saturating_add(self.terminal.size().height as usize),
+                ),
+            );
+            self.update_comparison();
+            if let Some(ref mut compare) = self.compare {
+                compare.highlight_other(
+                    self.offset.y,
+                    self.terminal.size().height as usize,
+                    self.search_options,
+                );
+            }
+            self.draw_rows();
+            self.draw_status_bar();
+            self.draw_message_bar();
+            let mut x = self
+                .document
+                .render_x(&self.cursor_position, self.tab_width);
+            if self.hex_nibble {
+                x = x.saturating_add(1);
+            }
+            if let Some(column) = self.prompt_cursor {
+                Terminal::set_cursor_position(&Position {
+                    x: column,
+                    y: (self.terminal.size().height as usize).saturating_add(1),
+                });
+            } else {
+                Terminal::set_cursor_position(&Position {
+                    x: x.saturating_sub(self.offset.x)
+                        .saturating_add(self.gutter_width()),
+                    y: self.screen_row(self.cursor_position.y),
+                });
+            }
+        }
+        Terminal::cursor_show();
+        Terminal::flush()
+    }
+
+    pub fn draw_row(&self, row: &Row, y: usize) {
+        let width = self.text_width();
+        let start = self.offset.x;
+        let end = self.offset.x.saturating_add(width);
+        let selection = self.selection_in_row(y);
+        let cursors = self.cursors.columns_in_row(y);
+        let conflict = self.document.conflict_highlighting(y);
+        let mut text = row.render_with_background(start, end, self.tab_width, conflict, |index| {
+            if cursors.contains(&index) {
+                Some(EXTRA_CURSOR_BG_COLOR)
+            } else {
+                selection
+                    .filter(|&(from, to)| from <= index && index < to)
+                    .map(|_| SELECTION_BG_COLOR)
+            }
+        });
+        // Cursor after the last grapheme is drawn on the following column
+        let row_width = row.width_until(row.len(), self.tab_width);
+        if cursors.contains(&row.len()) && row_width >= start && row_width < end {
+            text.push_str(&format!(
+                "{} {}",
+                color::Bg(EXTRA_CURSOR_BG_COLOR),
+                color::Bg(color::Reset)
+            ));
+        }
+        if let Some(blame) = self.document.blame() {
+            Terminal::set_fg_color(GUTTER_FG_COLOR);
+            print!(
+                "{}",
+                blame_annotation(blame.get(y).and_then(Option::as_ref))
+            );
+            Terminal::reset_fg_color();
+        }
+        if let Some(changes) = self.document.git_changes() {
+            let (marker, color) = match changes.marker(y) {
+                Some(Marker::Added) => ('+', ADDED_COLOR),
+                Some(Marker::Modified) => ('~', MODIFIED_COLOR),
+                Some(Marker::Deleted) => ('-', DELETED_COLOR),
+                None => (' ', GUTTER_FG_COLOR),
+            };
+            Terminal::set_fg_color(color);
+            print!("{}", marker);
+            Terminal::reset_fg_color();
+            print!(" ");
+        }
+        println!("{}\r", text);
+    }
+
+    /// Number of columns left of text taken by blame and git change markers.
+    /// When files are compared it's the column where the pane of the current document starts.
+    fn gutter_width(&self) -> usize {
+        if let Some(compare) = self.active_compare() {
+            return if compare.is_active_left() {
+                0
+            } else {
+                self.pane_width().saturating_add(1)
+            };
+        }
+        let blame = if self.document.blame().is_some() {
+            BLAME_WIDTH
+        } else {
+            0
+        };
+        let markers: usize = if self.document.git_changes().is_none() {
+            0
+        } else {
+            2
+        };
+        blame.saturating_add(markers)
+    }
+
+    /// Number of columns where text of the current document is shown
+    fn text_width(&self) -> usize {
+        let width = self.terminal.size().width as usize;
+        match self.active_compare() {
+            Some(compare) if compare.is_active_left() => self.pane_width(),
+            Some(_) => width.saturating_sub(self.pane_width().saturating_add(1)),
+            None => width.saturating_sub(self.gutter_width()),
+        }
+    }
+
+    /// Width of the left pane of compared files, the right one takes the rest after separator
+    fn pane_width(&self) -> usize {
+        (self.terminal.size().width as usize)
+            .saturating_sub(1)
+            .checked_div(2)
+            .unwrap_or(0)
+    }
+
+    /// Terminal row where row `y` of the current document is shown
+    fn screen_row(&self, y: usize) -> usize {
+        if let Some(compare) = self.active_compare() {
+            compare
+                .row_of(y)
+                .saturating_sub(compare.row_of(self.offset.y))
+        } else {
+            y.saturating_sub(self.offset.y)
+        }
+    }
+
+    /// Ordered start and end of selection, `None` if nothing is selected
+    fn selection_range(&self) -> Option<(Position, Position)> {
+        let anchor = self.selection.clone()?;
+        let cursor = self.cursor_position.clone();
+        if anchor == cursor || self.document.is_hex() {
+            return None;
+        }
+        if (anchor.y, anchor.x) < (cursor.y, cursor.x) {
+            Some((anchor, cursor))
+        } else {
+            Some((cursor, anchor))
+        }
+    }
+
+    /// Range of selected graphemes on row `y`
+    fn selection_in_row(&self, y: usize) -> Option<(usize, usize)> {
+        if let Some(block) = self.block() {
+            return self
+                .document
+                .row(y)
+                .filter(|_| y >= block.top && y <= block.bottom)
+                .map(|row| row.range_in_columns(block.left, block.right, self.tab_width));
+        }
+        let (start, end) = self.selection_range()?;
+        if y < start.y || y > end.y {
+            return None;
+        }
+        let from = if y == start.y { start.x } else { 0 };
+        let to = if y == end.y {
+            end.x
+        } else {
+            self.document.row_len(y)
+        };
+        Some((from, to))
+    }
+
+    #[allow(clippy::integer_division)]
+    fn draw_rows(&self) {
+        if let Some(compare) = self.active_compare() {
+            self.draw_compare_rows(compare);
+            return;
+        }
+        let height = self.terminal.size().height;
+        let text_height = height.saturating_sub(self.finder_height());
+        for terminal_row in 0..height {
+            Terminal::clear_current_line();
+            if terminal_row >= text_height {
+                self.draw_finder_row(terminal_row.saturating_sub(text_height) as usize);
+                continue;
+            }
+            if let Some(line) = self.popup_line(terminal_row as usize) {
+                self.draw_popup_line(line);
+                continue;
+            }
+            let y = self.offset.y.saturating_add(terminal_row as usize);
+            if let Some(row) = self.document.row(y) {
+                self.draw_row(row, y);
+            } else if self.document.is_empty() && terminal_row == height / 3 {
+                self.draw_welcome_message();
+            } else {
+                println!("~\r");
+            }
+        }
+    }
+
+    /// Line of popup shown at `terminal_row`. Popup is shown under the cursor, or above it if there isn't enough space.
+    fn popup_line(&self, terminal_row: usize) -> Option<&String> {
+        let lines = self.popup.as_ref()?;
+        let height = self.terminal.size().height as usize;
+        let cursor_row = self.screen_row(self.cursor_position.y);
+        let top = if cursor_row.saturating_add(1).saturating_add(lines.len()) <= height {
+            cursor_row.saturating_add(1)
+        } else {
+            cursor_row.saturating_sub(lines.len())
+        };
+        lines.get(terminal_row.checked_sub(top)?)
+    }
+
+    fn draw_popup_line(&self, line: &str) {
+        let width = self.terminal.size().width as usize;
+        let indent = " ".repeat(self.gutter_width());
+        let line: String = format!("{}{}", indent, line).chars().take(width).collect();
+        Terminal::set_bg_color(POPUP_BG_COLOR);
+        Terminal::set_fg_color(POPUP_FG_COLOR);
+        println!("{:width$}\r", line, width = width);
+        Terminal::reset_fg_color();
+        Terminal::reset_bg_color();
+    }
+
+    /// Draw compared files next to each other, lines are aligned so that equal lines are on the same row
+    fn draw_compare_rows(&self, compare: &Compare) {
+        let height = self.terminal.size().height as usize;
+        let width = self.terminal.size().width as usize;
+        let left_width = self.pane_width();
+        let widths = (
+            left_width,
+            width.saturating_sub(left_width.saturating_add(1)),
+        );
+        let first = compare.row_of(self.offset.y);
+        for terminal_row in 0..height {
+            Terminal::clear_current_line();
+            if let Some(line) = self.popup_line(terminal_row) {
+                self.draw_popup_line(line);
+                continue;
+            }
+            if let Some((left, right)) = compare.render_row(
+                &self.document,
+                self.offset.x,
+                first.saturating_add(terminal_row),
+                widths,
+                self.tab_width,
+            ) {
+                print!("{}", left);
+                Terminal::set_fg_color(GUTTER_FG_COLOR);
+                print!("│");
+                Terminal::reset_fg_color();
+                println!("{}\r", right);
+            } else {
+                println!("~\r");
+            }
+        }
+    }
+
+    /// Number of rows at the bottom of text area taken by the file finder
+    fn finder_height(&self) -> u16 {
+        self.finder.as_ref().map_or(0, |_| {
+            self.terminal
+                .size()
+                .height
+                .checked_div(2)
+                .unwrap_or(0)
+                .min(FINDER_HEIGHT)
+        })
+    }
+
+    /// Draw row `index` of the file finder. The first row shows number of matches,
+    /// the others matching files around the selected one.
+    fn draw_finder_row(&self, index: usize) {
+        let finder = if let Some(ref finder) = self.finder {
+            finder
+        } else {
+            return;
+        };
+        let width = self.terminal.size().width as usize;
+        let matches = finder.matches();
+        let selected = finder.selected_index();
+        let (line, highlighted) = if let Some(index) = index.checked_sub(1) {
+            let visible = (self.finder_height() as usize).saturating_sub(1).max(1);
+            let item = selected
+                .saturating_add(1)
+                .saturating_sub(visible)
+                .saturating_add(index);
+            match matches.get(item) {
+                Some(file) if item == selected => (format!("> {}", file), true),
+                Some(file) => (format!("  {}", file), false),
+                None => (String::new(), false),
+            }
+        } else {
+            let count = format!("-- {}/{} files ", matches.len(), finder.file_count());
+            let fill = "-".repeat(width.saturating_sub(count.len()));
+            (format!("{}{}", count, fill), true)
+        };
+        let line: String = line.chars().take(width).collect();
+        if highlighted {
+            Terminal::set_bg_color(STATUS_BG_COLOR);
+            Terminal::set_fg_color(STATUS_FG_COLOR);
+            println!("{:width$}\r", line, width = width);
+            Terminal::reset_fg_color();
+            Terminal::reset_bg_color();
+        } else {
+            println!("{}\r", line);
+        }
+    }
+
+    fn draw_welcome_message(&self) {
+        let mut welcome_message = format!("Hecto editor -- version {}", VERSION);
+        let width = self.terminal.size().width as usize;
+        let len = welcome_message.len();
+        #[allow(clippy::integer_division)]
+        let padding = width.saturating_sub(len.saturating_add(1)) / 2;
+        let spaces = " ".repeat(padding);
+        welcome_message = format!("~{}{}", spaces, welcome_message);
+        welcome_message.truncate(width);
+        println!("{}\r", welcome_message);
+    }
+
+    fn process_keypress(&mut self) -> io::Result<()> {
+        match self.read_event()? {
+            Event::Key(key) => {
+                if let Some(ref mut recording) = self.recording {
+                    recording.start_command();
+                }
+                self.process_key(key);
+            }
+            Event::Mouse(mouse) => {
+                self.process_mouse(mouse);
+                self.scroll();
+            }
+            Event::Unsupported(_) => (),
+        }
+        Ok(())
+    }
+
+    fn process_key(&mut self, pressed_key: Key) {
+        // Popup is closed by any key, ESC does nothing else
+        if self.popup.take().is_some() && pressed_key == Key::Esc {
+            return;
+        }
+        // Commands may work with selection
+        let selection = self.selection_range();
+        if pressed_key != Key::Ctrl('e') {
+            self.selection = None;
+        }
+        let revision = self.document.revision();
+        let mut multiple_cursors = false;
+        let mut block_edit = false;
+        match pressed_key {
+            key if self.macros.bindings.contains_key(&key) => {
+                if let Some(name) = self.macros.bindings.get(&key).cloned() {
+                    self.play_macro(&name, Some(1));
+                }
+            }
+            Key::Ctrl('q') | Key::Esc if !self.hidden_buffers.is_empty() => self.close_view(),
+            key if self.is_cursor_key(key) => {
+                self.process_cursor_key(key, selection);
+                multiple_cursors = true;
+            }
+            key if self.is_block_key(key) => {
+                self.process_block_key(key);
+                block_edit = true;
+            }
+            Key::Char('\n') if self.document.location(self.cursor_position.y).is_some() => {
+                self.open_location();
+            }
+            Key::Char('\n') if self.is_diff_view() => self.open_diff_line(),
+            Key::Char(c) if self.document.listing().is_some() => self.browser_command(c),
+            Key::Backspace if self.document.listing().is_some() => self.browser_command('-'),
+            Key::Char(_)
+            | Key::Delete
+            | Key::Backspace
+            | Key::Ctrl('w' | 'x' | 'v')
+            | Key::Alt('\x7f' | 'd')
+            | Key::BackTab
+                if self.document.is_read_only() =>
+            {
+                self.status_message = StatusMessage::from("Buffer is read-only.".to_owned());
+            }
+            Key::Ctrl('q') => {
+                let other_dirty = self
+                    .compare
+                    .as_ref()
+                    .is_some_and(|compare| compare.other().is_dirty());
+                #[allow(clippy::integer_arithmetic)]
+                if self.quit_times > 0 && (self.document.is_dirty() || other_dirty) {
+                    self.status_message = StatusMessage::from(format!(
+                        "WARNING! File has unsaved changes. Press Ctrl-Q {} more times to quit.",
+                        self.quit_times
+                    ));
+                    self.quit_times -= 1;
+                    return;
+                }
+                self.should_quit = true;
+            }
+            Key::Ctrl('s') => self.save(),
+            Key::Ctrl('o') => self.open(""),
+            Key::Ctrl('p') => self.find_file(),
+            Key::Alt('g') => self.grep(""),
+            Key::Ctrl('n') => self.new_buffer(),
+            Key::Ctrl('e') => self.command_line(),
+            Key::Ctrl('g') => {
+                let arguments = self
+                    .prompt("Go to (line[:col], +N, -N, N%): ", |_, _, _| {})
+                    .unwrap_or(None);
+                if let Some(arguments) = arguments {
+                    self.goto(&arguments);
+                }
+            }
+            Key::Ctrl('f') => {
+                if self.document.is_hex() {
+                    self.hex_search();
+                } else {
+                    self.search();
+                }
+            }
+            Key::Alt('.') => self.next_hunk(),
+            Key::Alt(',') => self.previous_hunk(),
+            Key::Alt('o') => self.switch_side(),
+            Key::Alt('r') => self.record(""),
+            Key::Alt('m') => self.play(""),
+            _ => self.process_editing_key(pressed_key, selection),
+        }
+        // Other edits work with the main cursor only and could leave the others out of text
+        if !multiple_cursors && revision != self.document.revision() {
+            self.cursors.clear();
+        }
+        if !block_edit && revision != self.document.revision() {
+            self.block_anchor = None;
+        }
+        if self.document.is_blame_outdated() {
+            self.schedule_blame_update();
+        }
+        self.scroll();
+        if self.quit_times < QUIT_TIMES {
+            self.quit_times = QUIT_TIMES;
+            self.status_message = StatusMessage::from(String::new());
+        }
+    }
+
+    /// Key handled by multiple cursors: adding them, or typing, deleting and moving at all of them
+    fn is_cursor_key(&self, key: Key) -> bool {
+        let editable = !self.cursors.is_empty()
+            && !self.document.is_read_only()
+            && !self.document.is_hex()
+            && self.block_anchor.is_none();
+        match key {
+            Key::Ctrl('d') | Key::AltUp | Key::AltDown | Key::Alt('a') => !self.document.is_hex(),
+            Key::Esc
+            | Key::Up
+            | Key::Down
+            | Key::Left
+            | Key::Right
+            | Key::Home
+            | Key::End
+            | Key::CtrlLeft
+            | Key::CtrlRight => !self.cursors.is_empty(),
+            Key::Char(_) | Key::Backspace | Key::Delete => editable,
+            _ => false,
+        }
+    }
+
+    fn process_cursor_key(&mut self, key: Key, selection: Option<(Position, Position)>) {
+        match key {
+            Key::Esc => self.cursors.clear(),
+            Key::Ctrl('d') => self.add_cursor_at_next_match(selection),
+            Key::AltUp | Key::AltDown => self.add_cursor_in_column(key == Key::AltDown),
+            Key::Alt('a') => self.add_cursors_at_all_matches(selection),
+            _ => self.process_key_at_cursors(key),
+        }
+    }
+
+    /// Key handled by block selection: starting it, pasting a block, or copying and changing the selected one
+    fn is_block_key(&self, key: Key) -> bool {
+        let selected = self.block_anchor.is_some();
+        match key {
+            Key::Ctrl('b' | 'v') => !self.document.is_hex(),
+            Key::Esc | Key::Ctrl('c' | 'x') => selected,
+            // Typing in read-only buffers is handled by file browser or refused
+            Key::Char(c) => c != '\n' && selected && !self.document.is_read_only(),
+            Key::Backspace | Key::Delete => selected && !self.document.is_read_only(),
+            _ => false,
+        }
+    }
+
+    fn process_block_key(&mut self, key: Key) {
+        match key {
+            Key::Esc => self.block_anchor = None,
+            Key::Ctrl('b') => self.toggle_block(),
+            Key::Ctrl('c') => self.copy_block(false),
+            Key::Ctrl('x') => self.copy_block(true),
+            Key::Ctrl('v') => self.paste_block(),
+            Key::Char(c) => self.insert_in_block(&c.to_string()),
+            _ => self.delete_in_block(key == Key::Backspace),
+        }
+    }
+
+    /// Typing, deleting and moving with the main cursor
+    fn process_editing_key(&mut self, key: Key, selection: Option<(Position, Position)>) {
+        match key {
+            Key::Insert if self.document.is_hex() => self.hex_insert = !self.hex_insert,
+            Key::Char(c) if self.document.is_hex() => self.hex_input(c),
+            Key::Delete if self.document.is_hex() => {
+                self.document.hex_delete_byte(&self.cursor_position);
+            }
+            Key::Backspace if self.document.is_hex() => {
+                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
+                    self.move_cursor(Key::Left);
+                    self.document.hex_delete_byte(&self.cursor_position);
+                }
+            }
+            Key::Char(c) if self.terminal.is_pasting() => {
+                self.cursor_position = self.document.insert_pasted(&self.cursor_position, c);
+            }
+            Key::Char('\t') => self.indent(selection),
+            Key::BackTab if !self.document.is_hex() => self.dedent(selection),
+            Key::Char(c) => {
+                self.cursor_position = self.document.insert(&self.cursor_position, c);
+            }
+            Key::Delete => self.document.delete(&self.cursor_position),
+            Key::Ctrl('w') | Key::Alt('\x7f') | CTRL_BACKSPACE if !self.document.is_hex() => {
+                let start = self.document.previous_word_start(&self.cursor_position);
+                self.document.delete_range(&start, &self.cursor_position);
+                self.cursor_position = start;
+            }
+            Key::Alt('d') | CTRL_DELETE if !self.document.is_hex() => {
+                let end = self.document.next_word_end(&self.cursor_position);
+                self.document.delete_range(&self.cursor_position, &end);
+            }
+            Key::Backspace => {
+                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
+                    self.move_cursor(Key::Left);
+                    self.document.delete(&self.cursor_position);
+                }
+            }
+            Key::Up
+            | Key::Down
+            | Key::Left
+            | Key::Right
+            | Key::PageUp
+            | Key::PageDown
+            | Key::End
+            | Key::Home
+            | Key::CtrlLeft
+            | Key::CtrlRight
+            | Key::CtrlUp
+            | Key::CtrlDown
+            | Key::CtrlHome
+            | Key::CtrlEnd => self.move_cursor(key),
+            _ => {}
+        }
+    }
+
+    fn process_mouse(&mut self, mouse: MouseEvent) {
+        match mouse {
+            MouseEvent::Press(MouseButton::Left, column, row) => {
+                if usize::from(row) > self.terminal.size().height as usize {
+                    return;
+                }
+                let position = self.position_on_screen(column, row);
+                self.hex_nibble = false;
+                self.cursors.clear();
+                let now = Instant::now();
+                let double_click = self.last_click.as_ref().is_some_and(|(time, last)| {
+                    now.duration_since(*time) < DOUBLE_CLICK_DURATION && *last == position
+                });
+                if double_click && !self.document.is_hex() {
+                    let (start, end) = self
+                        .document
+                        .row(position.y)
+                        .map_or((0, 0), |row| row.word_at(position.x));
+                    self.selection = Some(Position {
+                        x: start,
+                        y: position.y,
+                    });
+                    self.cursor_position = Position {
+                        x: end,
+                        y: position.y,
+                    };
+                    self.last_click = None;
+                } else {
+                    self.selection = Some(position.clone());
+                    self.cursor_position = position.clone();
+                    self.last_click = Some((now, position));
+                    self.dragging = true;
+                }
+            }
+            MouseEvent::Hold(column, row) if self.dragging => {
+                let mut position = self.position_on_screen(column, row);
+                if row <= 1 && self.offset.y > 0 {
+                    // Dragging at the top line scrolls up
+                    position = self.document.position_at(
+                        self.offset.y.saturating_sub(1),
+                        self.text_column(column),
+                        self.tab_width,
+                    );
+                }
+                self.cursor_position = position;
+            }
+            MouseEvent::Release(..) => self.dragging = false,
+            MouseEvent::Press(MouseButton::WheelUp, ..) => self.scroll_view(false),
+            MouseEvent::Press(MouseButton::WheelDown, ..) => self.scroll_view(true),
+            _ => (),
+        }
+    }
+
+    /// Document position drawn at one-based screen coordinates. Coordinates outside of text area are clamped to it.
+    fn position_on_screen(&self, column: u16, row: u16) -> Position {
+        let height = self.terminal.size().height as usize;
+        let row = usize::from(row).saturating_sub(1);
+        // Dragging below the text area selects the line after the view, which makes it scroll down
+        let mut y = self.offset.y.saturating_add(row.min(height));
+        if let Some(compare) = self.active_compare() {
+            let top_row = compare.row_of(self.offset.y);
+            y = compare.line_at(top_row.saturating_add(row.min(height)));
+        }
+        self.document
+            .position_at(y, self.text_column(column), self.tab_width)
+    }
+
+    /// Column of text drawn at one-based screen column, taking gutter and horizontal scrolling into account
+    fn text_column(&self, column: u16) -> usize {
+        usize::from(column)
+            .saturating_sub(1)
+            .saturating_sub(self.gutter_width())
+            .saturating_add(self.offset.x)
+    }
+
+    /// Scroll by mouse wheel, the cursor is moved to stay in the view
+    fn scroll_view(&mut self, down: bool) {
+        let height = self.terminal.size().height as usize;
+        let max_offset = self.document.len().saturating_sub(1);
+        self.offset.y = if down {
+            self.offset
+                .y
+                .saturating_add(WHEEL_SCROLL_LINES)
+                .min(max_offset)
+        } else {
+            self.offset.y.saturating_sub(WHEEL_SCROLL_LINES)
+        };
+        let last_visible = self.offset.y.saturating_add(height).saturating_sub(1);
+        let y = self
+            .cursor_position
+            .y
+            .clamp(self.offset.y, last_visible)
+            .min(max_offset);
+        if y != self.cursor_position.y {
+            let x = cmp::min(self.cursor_position.x, self.document.row_len(y));
+            self.cursor_position = Position { x, y };
+        }
+    }
+
+    /// Wait for a key, mouse events are ignored
+    fn read_key(&mut self) -> io::Result<Key> {
+        loop {
+            if let Event::Key(key) = self.read_event()? {
+                return Ok(key);
+            }
+        }
+    }
+
+    /// Wait for keyboard or mouse event. Meanwhile due timers are run and the screen is redrawn
+    /// when terminal window is resized or status message expires.
+    fn read_event(&mut self) -> io::Result<Event> {
+        if let Some(key) = self.replay.read_key() {
+            return Ok(Event::Key(key));
+        }
+        let event = self.read_terminal_event()?;
+        if let (Some(recording), &Event::Key(key)) = (self.recording.as_mut(), &event) {
+            recording.push(key);
+        }
+        Ok(event)
+    }
+
+    fn read_terminal_event(&mut self) -> io::Result<Event> {
+        loop {
+            let now = Instant::now();
+            let message_expiry = self
+                .status_message
+                .time
+                .checked_add(STATUS_MESSAGE_DURATION)
+                .unwrap_or(now);
+            let wake_up = self
+                .timers
+                .next_run()
+                .into_iter()
+                .chain((message_expiry > now).then_some(message_expiry))
+                .min();
+            let timeout = wake_up.map(|wake_up| wake_up.saturating_duration_since(now));
+            if let Some(event) = self.terminal.read_event(timeout)? {
+                return Ok(event);
+            }
+            if self.terminal.update_size()? {
+                self.scroll();
+                Terminal::clear_screen();
+            }
+            for task in self.timers.due(Instant::now()) {
+                task(self)?;
+            }
+            self.refresh_screen()?;
+        }
+    }
+
+    fn save(&mut self) {
+        if self.document.is_read_only() {
+            self.status_message = StatusMessage::from("Buffer is read-only.".to_owned());
+            return;
+        }
+        if self.document.file_name.is_none() {
+            self.save_as("");
+            return;
+        } else if self.document.changed_on_disk()
+            && self
+                .choose(
+                    "File changed on disk since it was opened. Overwrite it? (y/n)",
+                    &['y', 'n'],
+                )
+                .unwrap_or(None)
+                != Some('y')
+        {
+            self.status_message = StatusMessage::from("Save aborted.".to_owned());
+            return;
+        }
+
+        if self.document.save().is_ok() {
+            self.status_message = StatusMessage::from("File saved successfully.".to_owned());
+        } else {
+            self.status_message = StatusMessage::from("Error writing file!".to_owned());
+        }
+    }
+
+    /// Read command line with fuzzy completion of command names (Tab) and history (Up/Down), then execute it
+    fn command_line(&mut self) {
+        self.prompt_hint = Some(command_hint(""));
+        let line = self
+            .prompt(": ", |editor, key, line| {
+                let (name, arguments) = command::split(line);
+                let typing_name = !line.trim_start().contains(char::is_whitespace);
+                let mut hint = None;
+                if key == Key::Char('\t') {
+                    if typing_name {
+                        if let Some(command) = command::matching(name).first() {
+                            *line = format!("{} ", command.name);
+                        }
+                    } else if let Some(command) = command::find(name) {
+                        if command.arguments.contains("<file>")
+                            || command.arguments.contains("<directory>")
+                        {
+                            let (path, names) = prompt::complete_path(arguments);
+                            *line = format!("{} {}", command.name, path);
+                            hint = Some(completion_hint(&names));
+                        }
+                    }
+                }
+                editor.prompt_hint = hint.or_else(|| Some(command_hint(line)));
+            })
+            .unwrap_or(None);
+        if let Some(line) = line {
+            self.execute_command(&line);
+        }
+    }
+
+    fn execute_command(&mut self, line: &str) {
+        let (name, arguments) = command::split(line);
+        let command = if let Some(command) = command::find(name) {
+            command
+        } else {
+            self.status_message = StatusMessage::from(format!("Unknown command: {}", name));
+            return;
+        };
+        // Optional arguments are written in brackets
+        if !command.arguments.is_empty()
+            && !command.arguments.starts_with('[')
+            && arguments.is_empty()
+        {
+            self.status_message =
+                StatusMessage::from(format!("Usage: {} {}", command.name, command.arguments));
+            return;
+        }
+        if self.macro_command(command.name, arguments)
+            || self.compare_command(command.name)
+            || self.cursor_command(command.name)
+            || self.block_command(command.name, arguments)
+        {
+            return;
+        }
+        match command.name {
+            "goto" => self.goto(arguments),
+            "set" => self.set_option(arguments),
+            "save" => self.save(),
+            "saveas" => self.save_as(arguments),
+            "open" => self.open(arguments),
+            "new" => self.new_buffer(),
+            "browse" => self.browse(arguments),
+            "files" => self.find_file(),
+            "grep" => self.grep(arguments),
+            "reload" => {
+                if self.confirm_discard() {
+                    self.reload();
+                }
+            }
+            "sort" => self.sort_lines(),
+            "diff" => self.show_disk_diff(),
+            "next-hunk" => self.next_hunk(),
+            "prev-hunk" => self.previous_hunk(),
+            "revert-hunk" => self.revert_hunk(),
+            "show-hunk" => self.show_hunk(),
+            "next-conflict" => self.next_conflict(),
+            "prev-conflict" => self.previous_conflict(),
+            "resolve" => self.resolve_conflict(arguments),
+            "blame" => self.toggle_blame(),
+            "blame-commit" => self.show_blame_commit(),
+            "write-region" => self.write_region(arguments),
+            "indent-tabs" => self.convert_indentation(Indentation::Tabs),
+            "indent-spaces" => {
+                let width = if arguments.is_empty() {
+                    Some(match self.document.indentation_style() {
+                        Indentation::Spaces(width) => width,
+                        Indentation::Tabs => self.tab_width,
+                    })
+                } else {
+                    arguments
+                        .trim()
+                        .parse()
+                        .ok()
+                        .filter(|width| (1..=16).contains(width))
+                };
+                if let Some(width) = width {
+                    self.convert_indentation(Indentation::Spaces(width));
+                } else {
+                    self.status_message =
+                        StatusMessage::from(format!("Invalid indentation width: {}", arguments));
+                }
+            }
+            "filetype" => {
+                if let Some(file_type) = FileType::from_name(arguments) {
+                    self.document.set_file_type(file_type);
+                } else {
+                    self.status_message =
+                        StatusMessage::from(format!("Unknown file type: {}", arguments));
+                }
+            }
+            "quit" => self.process_key(Key::Ctrl('q')),
+            _ => (),
+        }
+    }
+
+    /// Move cursor to location written as `line[:column]`, `+N`, `-N` or `N%`
+    fn goto(&mut self, arguments: &str) {
+        if let Some(location) = Location::parse(arguments) {
+            self.go_to(&location);
+        } else {
+            self.status_message = StatusMessage::from(format!("Invalid location: {}", arguments));
+        }
+    }
+
+    /// Move cursor to `location` and show it in the middle of screen
+    fn go_to(&mut self, location: &Location) {
+        let y = location.line_index(self.cursor_position.y, self.document.len());
+        let x = location
+            .column
+            .map_or(0, |column| column.saturating_sub(1))
+            .min(self.document.row_len(y));
+        self.cursor_position = Position { x, y };
+        self.center_cursor();
+    }
+
+    /// Scroll so that the cursor is in the middle of screen
+    fn center_cursor(&mut self) {
+        let height = self.terminal.size().height as usize;
+        self.offset.y = self
+            .cursor_position
+            .y
+            .saturating_sub(height.checked_div(2).unwrap_or(0));
+        self.scroll();
+    }
+
+    fn set_option(&mut self, arguments: &str) {
+        match arguments.split_once('=') {
+            Some(("tabwidth", value)) => match value.trim().parse::<usize>() {
+                Ok(width) if (1..=16).contains(&width) => self.tab_width = width,
+                _ => {
+                    self.status_message =
+                        StatusMessage::from(format!("Invalid tab width: {}", value));
+                }
+            },
+            Some(("indent", value)) => {
+                if let Some(indentation) = parse_indentation(value) {
+                    self.document.set_indentation_style(indentation);
+                } else {
+                    self.status_message =
+                        StatusMessage::from(format!("Invalid indentation: {}", value));
+                }
+            }
+            _ => {
+                self.status_message = StatusMessage::from(format!("Unknown option: {}", arguments));
+            }
+        }
+    }
+
+    /// Save document under new name, which is asked for when `file_name` is empty
+    fn save_as(&mut self, file_name: &str) {
+        if self.document.is_read_only() {
+            self.status_message = StatusMessage::from("Buffer is read-only.".to_owned());
+            return;
+        }
+        if file_name.is_empty() {
+            if let Some(file_name) = self.prompt("Save as: ", complete_file_name).unwrap_or(None) {
+                self.save_as(&file_name);
+            } else {
+                self.status_message = StatusMessage::from("Save aborted.".to_owned());
+            }
+            return;
+        }
+        if Path::new(file_name).exists()
+            && self
+                .choose("File already exists. Overwrite it? (y/n)", &['y', 'n'])
+                .unwrap_or(None)
+                != Some('y')
+        {
+            self.status_message = StatusMessage::from("Save aborted.".to_owned());
+            return;
+        }
+        if self.document.save_as(file_name).is_ok() {
+            self.status_message = StatusMessage::from("File saved successfully.".to_owned());
+            self.document.set_git_base(git::head_lines(file_name));
+        } else {
+            self.status_message = StatusMessage::from("Error writing file!".to_owned());
+        }
+    }
+
+    /// Replace current document by file `file_name`, which is asked for when it's empty
+    fn open(&mut self, file_name: &str) {
+        if !self.confirm_discard() || !self.confirm_discard_compared() {
+            return;
+        }
+        if file_name.is_empty() {
+            if let Some(file_name) = self.prompt("Open: ", complete_file_name).unwrap_or(None) {
+                self.open_confirmed(&file_name);
+            }
+            return;
+        }
+        self.open_confirmed(file_name);
+    }
+
+    /// Replace current document by file `file_name` without asking about unsaved changes
+    fn open_confirmed(&mut self, file_name: &str) {
+        match open_document(file_name) {
+            Ok(document) => {
+                self.status_message =
+                    StatusMessage::from(document_help(&document).unwrap_or("").to_owned());
+                self.document = document;
+                self.cursor_position = Position::default();
+                self.offset = Position::default();
+                self.compare = None;
+            }
+            Err(_) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not open file: {}", file_name));
+            }
+        }
+    }
+
+    /// Find file in project by fuzzy matching of its path and open it. Selected file is previewed.
+    fn find_file(&mut self) {
+        self.finder = Some(Finder::new(Path::new(".")));
+        self.open_view(Document::default());
+        self.preview_selected_file();
+        let mut filtered = String::new();
+        let (accepted, _) = self
+            .read_prompt("Find file (Ctrl-N/P select): ", |editor, key, pattern| {
+                if let Some(ref mut finder) = editor.finder {
+                    match key {
+                        Key::Ctrl('n') => finder.select_next(),
+                        Key::Ctrl('p') => finder.select_previous(),
+                        // Selection is kept when the cursor only moves in the pattern
+                        _ if *pattern != filtered => {
+                            finder.filter(pattern);
+                            pattern.clone_into(&mut filtered);
+                        }
+                        _ => (),
+                    }
+                }
+                editor.preview_selected_file();
+            })
+            .unwrap_or_default();
+        let selected = self
+            .finder
+            .take()
+            .and_then(|finder| finder.selected().map(str::to_owned));
+        self.close_view();
+        if let (true, Some(file_name)) = (accepted, selected) {
+            self.open(&file_name);
+        }
+    }
+
+    /// Search for `query` in all files under current directory, it's asked for when empty.
+    /// Results are shown in a location list while the search continues.
+    fn grep(&mut self, query: &str) {
+        let query = if query.is_empty() {
+            match self
+                .prompt("Search in files: ", |_, _, _| {})
+                .unwrap_or(None)
+            {
+                Some(query) => query,
+                None => return,
+            }
+        } else {
+            query.to_owned()
+        };
+        self.stop_grep();
+        let grep = Grep::new(Path::new("."), &query, self.search_options);
+        let timer = self.timers.register(Duration::ZERO, Self::continue_grep);
+        self.grep = Some((grep, timer));
+        self.open_view(Document::location_list(&format!("grep {}", query)));
+        self.status_message = StatusMessage::from(format!("Searching for {}...", query));
+    }
+
+    /// Search next slice of files and append results to location list
+    #[allow(clippy::unnecessary_wraps)]
+    fn continue_grep(&mut self) -> io::Result<()> {
+        let (grep, _) = if let Some(ref mut grep) = self.grep {
+            grep
+        } else {
+            return Ok(());
+        };
+        // Location list was closed
+        if !self.document.is_location_list() {
+            self.stop_grep();
+            return Ok(());
+        }
+        for found in grep.search(GREP_TIME_SLICE) {
+            let line = format!(
+                "{}:{}:{}: {}",
+                found.file_name,
+                found.position.y.saturating_add(1),
+                found.position.x.saturating_add(1),
+                found.line.trim()
+            );
+            self.document
+                .push_location(&line, Some((found.file_name, found.position)));
+        }
+        let status = format!(
+            "{} matches in {} files",
+            grep.match_count(),
+            grep.file_count()
+        );
+        if grep.is_done() {
+            self.status_message = StatusMessage::from(format!("Found {}", status));
+            self.stop_grep();
+        } else {
+            self.status_message = StatusMessage::from(format!("Searching... {}", status));
+        }
+        Ok(())
+    }
+
+    fn stop_grep(&mut self) {
+        if let Some((_, timer)) = self.grep.take() {
+            self.timers.cancel(timer);
+        }
+    }
+
+    /// Open file at location which current row of location list points to
+    fn open_location(&mut self) {
+        let (file_name, position) =
+            if let Some(location) = self.document.location(self.cursor_position.y) {
+                location.clone()
+            } else {
+                return;
+            };
+        self.stop_grep();
+        if !self.hidden_buffers.is_empty() {
+            self.close_view();
+        }
+        if self.document.file_name.as_deref() != Some(file_name.as_str()) {
+            self.open(&file_name);
+            if self.document.file_name.as_deref() != Some(file_name.as_str()) {
+                return;
+            }
+        }
+        self.go_to(&Location {
+            line: LocationLine::Absolute(position.y.saturating_add(1)),
+            column: Some(position.x.saturating_add(1)),
+        });
+    }
+
+    /// Show file selected in the file finder instead of current document
+    fn preview_selected_file(&mut self) {
+        let selected = self.finder.as_ref().and_then(Finder::selected);
+        if selected == self.document.file_name.as_deref() {
+            return;
+        }
+        self.document = match selected {
+            Some(file_name) => Document::open(file_name).unwrap_or_else(|_| {
+                Document::view(file_name, &["(binary or unreadable file)".to_owned()])
+            }),
+            None => Document::default(),
+        };
+        self.cursor_position = Position::default();
+        self.offset = Position::default();
+    }
+
+    /// Show directory in the file browser, by default the one containing current file
+    fn browse(&mut self, directory: &str) {
+        let directory = if directory.is_empty() {
+            self.document
+                .file_name
+                .as_ref()
+                .and_then(|file_name| Path::new(file_name).parent())
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+        } else {
+            PathBuf::from(directory)
+        };
+        self.show_directory(&directory, None);
+    }
+
+    /// Show listing of `directory` with cursor at entry `select`. The listing replaces
+    /// current document if it's a listing too, otherwise it's opened as a view.
+    fn show_directory(&mut self, directory: &Path, select: Option<&str>) {
+        let document = match Document::open_directory(directory) {
+            Ok(document) => document,
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!(
+                    "ERR: Could not read directory {}: {}",
+                    directory.display(),
+                    error
+                ));
+                return;
+            }
+        };
+        let y = select
+            .and_then(|name| document.listing()?.row_of(name))
+            .unwrap_or(browser::HEADER_ROWS);
+        if self.document.listing().is_some() {
+            self.document = document;
+            self.offset = Position::default();
+        } else {
+            self.open_view(document);
+        }
+        self.cursor_position = Position {
+            x: 0,
+            y: y.min(self.document.len().saturating_sub(1)),
+        };
+        self.scroll();
+        self.status_message = StatusMessage::from(BROWSER_HELP.to_owned());
+    }
+
+    /// Handle key pressed in the file browser
+    fn browser_command(&mut self, c: char) {
+        let (directory, entry) = if let Some(listing) = self.document.listing() {
+            let entry = listing
+                .entry(self.cursor_position.y)
+                .map(|entry| (entry.name.clone(), entry.is_dir));
+            (listing.directory().to_path_buf(), entry)
+        } else {
+            return;
+        };
+        match (c, entry) {
+            ('\n', Some((name, true))) => self.browser_enter(&name),
+            ('\n', Some((name, false))) => {
+                let path = self.document.listing().map(|listing| listing.path(&name));
+                if let Some(path) = path {
+                    let path = path.to_string_lossy().into_owned();
+                    if self.hidden_buffers.is_empty() {
+                        self.open_confirmed(&path);
+                    } else {
+                        self.close_view();
+                        self.open(&path);
+                    }
+                }
+            }
+            ('-', _) => self.browser_enter(".."),
+            ('n', _) => self.browser_create(&directory),
+            ('r', Some((name, _))) if name != ".." => self.browser_rename(&directory, &name),
+            ('d', Some((name, is_dir))) if name != ".." => {
+                self.browser_delete(&directory, &name, is_dir);
+            }
+            ('g', entry) => {
+                let name = entry.map(|(name, _)| name);
+                self.show_directory(&directory, name.as_deref());
+            }
+            _ => self.status_message = StatusMessage::from(BROWSER_HELP.to_owned()),
+        }
+    }
+
+    /// Show listing of subdirectory `name`, or of parent directory for `..` with cursor
+    /// at the directory which was left
+    fn browser_enter(&mut self, name: &str) {
+        let (path, left) = if let Some(listing) = self.document.listing() {
+            let left = if name == ".." {
+                fs::canonicalize(listing.directory())
+                    .ok()
+                    .and_then(|directory| {
+                        Some(directory.file_name()?.to_string_lossy().into_owned())
+                    })
+            } else {
+                None
+            };
+            (listing.path(name), left)
+        } else {
+            return;
+        };
+        self.show_directory(&path, left.as_deref());
+    }
+
+    /// Create file or directory (name ending with `/`) in listed `directory`
+    fn browser_create(&mut self, directory: &Path) {
+        let name = if let Some(name) = self
+            .prompt("Create (end with / for directory): ", |_, _, _| {})
+            .unwrap_or(None)
+        {
+            name
+        } else {
+            return;
+        };
+        let path = directory.join(&name);
+        let result = if name.ends_with('/') {
+            fs::create_dir_all(&path)
+        } else {
+            fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .map(|_| ())
+        };
+        match result {
+            Ok(()) => {
+                let created = name.split('/').next().unwrap_or_default();
+                self.show_directory(directory, Some(created));
+                self.status_message = StatusMessage::from(format!("Created {}", name));
+            }
+            Err(error) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not create {}: {}", name, error));
+            }
+        }
+    }
+
+    fn browser_rename(&mut self, directory: &Path, name: &str) {
+        let new_name = if let Some(new_name) = self
+            .prompt(&format!("Rename {} to: ", name), |_, _, _| {})
+            .unwrap_or(None)
+        {
+            new_name
+        } else {
+            return;
+        };
+        let target = directory.join(&new_name);
+        if target.exists()
+            && self
+                .choose(
+                    &format!("{} already exists. Overwrite it? (y/n)", new_name),
+                    &['y', 'n'],
+                )
+                .unwrap_or(None)
+                != Some('y')
+        {
+            return;
+        }
+        match fs::rename(directory.join(name), &target) {
+            Ok(()) => {
+                let renamed = new_name.split('/').next().unwrap_or_default();
+                self.show_directory(directory, Some(renamed));
+                self.status_message =
+                    StatusMessage::from(format!("Renamed {} to {}", name, new_name));
+            }
+            Err(error) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not rename {}: {}", name, error));
+            }
+        }
+    }
+
+    fn browser_delete(&mut self, directory: &Path, name: &str, is_dir: bool) {
+        let question = if is_dir {
+            format!("Delete directory {} and all its contents? (y/n)", name)
+        } else {
+            format!("Delete {}? (y/n)", name)
+        };
+        if self.choose(&question, &['y', 'n']).unwrap_or(None) != Some('y') {
+            return;
+        }
+        let path = directory.join(name);
+        let result = if is_dir {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match result {
+            Ok(()) => {
+                let y = self.cursor_position.y;
+                self.show_directory(directory, None);
+                self.cursor_position.y = y.min(self.document.len().saturating_sub(1));
+                self.scroll();
+                self.status_message = StatusMessage::from(format!("Deleted {}", name));
+            }
+            Err(error) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not delete {}: {}", name, error));
+            }
+        }
+    }
+
+    /// Replace current document by an empty one
+    fn new_buffer(&mut self) {
+        if !self.confirm_discard() || !self.confirm_discard_compared() {
+            return;
+        }
+        self.document = Document::default();
+        self.cursor_position = Position::default();
+        self.offset = Position::default();
+        self.compare = None;
+        self.status_message = StatusMessage::from("New buffer.".to_owned());
+    }
+
+    /// Ask user whether unsaved changes of document may be discarded. Returns `true` if there are no changes.
+    fn confirm_discard(&mut self) -> bool {
+        !self.document.is_dirty()
+            || self
+                .choose("File has unsaved changes. Discard them? (y/n)", &['y', 'n'])
+                .unwrap_or(None)
+                == Some('y')
+    }
+
+    /// Indent selected rows one level. Without selection the cursor row is indented when the cursor
+    /// is in its indentation, otherwise indentation is inserted at the cursor up to the next level.
+    fn indent(&mut self, selection: Option<(Position, Position)>) {
+        let y = self.cursor_position.y;
+        let indentation_end = self.document.row(y).map_or(0, |row| {
+            row.get_string()
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .count()
+        });
+        if selection.is_some() || self.cursor_position.x <= indentation_end {
+            self.change_indentation(selection, false);
+            return;
+        }
+        self.cursor_position = self
+            .document
+            .insert_tab(&self.cursor_position, self.tab_width);
+    }
+
+    /// Remove one level of indentation from selected rows, or the cursor row
+    fn dedent(&mut self, selection: Option<(Position, Position)>) {
+        self.change_indentation(selection, true);
+    }
+
+    /// Indent or dedent selected rows, or the cursor row, keeping the selection so that it can be repeated
+    fn change_indentation(&mut self, selection: Option<(Position, Position)>, dedent: bool) {
+        let cursor = self.cursor_position.clone();
+        // Corner of selection opposite to the cursor
+        let anchor = selection.as_ref().map(|(start, end)| {
+            if *start == cursor {
+                end.clone()
+            } else {
+                start.clone()
+            }
+        });
+        let (start, end) = selection.unwrap_or_else(|| (cursor.clone(), cursor.clone()));
+        // Line where selection ends at its beginning isn't selected
+        let end_y = if end.x == 0 && end.y > start.y {
+            end.y.saturating_sub(1)
+        } else {
+            end.y
+        };
+        let mut shifts = Vec::new();
+        for y in start.y..=end_y {
+            let changed = if dedent {
+                self.document.dedent_row(y)
+            } else if self.document.row_len(y) > 0 || start.y == end_y {
+                self.document.indent_row(y)
+            } else {
+                0
+            };
+            shifts.push((y, changed));
+        }
+        let moved = |position: &Position| {
+            let changed = shifts
+                .iter()
+                .find(|&&(y, _)| y == position.y)
+                .map_or(0, |&(_, changed)| changed);
+            let x = if dedent {
+                position.x.saturating_sub(changed)
+            } else if position.x > 0 || anchor.is_none() {
+                position.x.saturating_add(changed)
+            } else {
+                0
+            };
+            Position { x, y: position.y }
+        };
+        if let Some(ref anchor) = anchor {
+            self.selection = Some(moved(anchor));
+        }
+        self.cursor_position = moved(&cursor);
+    }
+
+    /// Rewrite indentation of whole document in `indentation` style
+    fn convert_indentation(&mut self, indentation: Indentation) {
+        if self.document.is_read_only() || self.document.is_hex() {
+            self.status_message =
+                StatusMessage::from("Indentation of buffer can't be converted.".to_owned());
+            return;
+        }
+        let cursor_column = self
+            .document
+            .render_x(&self.cursor_position, self.tab_width);
+        self.document
+            .convert_indentation(indentation, self.tab_width);
+        self.cursor_position =
+            self.document
+                .position_at(self.cursor_position.y, cursor_column, self.tab_width);
+        self.status_message = StatusMessage::from(format!(
+            "Indentation converted to {}.",
+            indentation.name().to_lowercase()
+        ));
+    }
+
+    /// Sort selected lines, or all lines of document if nothing is selected
+    fn sort_lines(&mut self) {
+        if self.document.is_read_only() || self.document.is_hex() {
+            self.status_message = StatusMessage::from("Buffer can't be sorted.".to_owned());
+            return;
+        }
+        if let Some((start, end)) = self.selection_range() {
+            // Line where selection ends at its beginning isn't selected
+            let end_y = if end.x == 0 && end.y > start.y {
+                end.y.saturating_sub(1)
+            } else {
+                end.y
+            };
+            self.document.sort_rows(start.y, end_y);
+        } else {
+            self.document.sort_rows(0, self.document.len());
+        }
+        self.selection = None;
+        let x = cmp::min(
+            self.cursor_position.x,
+            self.document.row_len(self.cursor_position.y),
+        );
+        self.cursor_position.x = x;
+    }
+
+    /// Write selected text to file `file_name`
+    fn write_region(&mut self, file_name: &str) {
+        let text = if let Some((start, end)) = self.selection_range() {
+            self.document.text(&start, &end)
+        } else {
+            self.status_message = StatusMessage::from("Nothing is selected.".to_owned());
+            return;
+        };
+        self.status_message = StatusMessage::from(match std::fs::write(file_name, text) {
+            Ok(()) => format!("Selection written to {}.", file_name),
+            Err(_) => format!("ERR: Could not write file: {}", file_name),
+        });
+    }
+
+    /// Ask user what to do when the open file was changed by another program
+    fn check_disk_change(&mut self) -> io::Result<()> {
+        if self.prompt_active || !self.document.poll_disk_change() {
+            return Ok(());
+        }
+        if self.document.is_dirty() {
+            match self.choose(
+                "File changed on disk. (r)eload and discard your changes, (k)eep your version or show (d)iff?",
+                &['r', 'k', 'd'],
+            )? {
+                Some('r') => self.reload(),
+                Some('d') => {
+                    // The question isn't asked again when the diff view is closed
+                    self.document.mark_disk_change_seen();
+                    self.show_disk_diff();
+                }
+                _ => {
+                    self.document.mark_disk_change_seen();
+                    self.status_message = StatusMessage::from(
+                        "Keeping your version. Saving will ask before overwriting the file.".to_owned(),
+                    );
+                }
+            }
+        } else if self.choose("File changed on disk. Reload it? (y/n)", &['y', 'n'])? == Some('y') {
+            self.reload();
+        } else {
+            self.document.mark_disk_change_seen();
+        }
+        Ok(())
+    }
+
+    /// Replace document by its current version on disk
+    fn reload(&mut self) {
+        let file_name = if let Some(ref file_name) = self.document.file_name {
+            file_name.clone()
+        } else {
+            return;
+        };
+        let document = if self.document.is_hex() {
+            Document::open_hex(&file_name)
+        } else {
+            open_document(&file_name)
+        };
+        if let Ok(document) = document {
+            self.document = document;
+            let y = cmp::min(
+                self.cursor_position.y,
+                self.document.len().saturating_sub(1),
+            );
+            let x = cmp::min(self.cursor_position.x, self.document.row_len(y));
+            self.cursor_position = Position { x, y };
+            self.scroll();
+            self.status_message = StatusMessage::from("File reloaded.".to_owned());
+        } else {
+            self.document.mark_disk_change_seen();
+            self.status_message =
+                StatusMessage::from(format!("ERR: Could not reload file: {}", file_name));
+        }
+    }
+
+    /// Show unified diff between the file on disk and the document being edited
+    fn show_disk_diff(&mut self) {
+        let file_name = if let Some(ref file_name) = self.document.file_name {
+            file_name.clone()
+        } else {
+            return;
+        };
+        let on_disk = if self.document.is_hex() {
+            Document::open_hex(&file_name)
+        } else {
+            Document::open(&file_name)
+        };
+        let on_disk_lines = on_disk.map(|document| document.lines()).unwrap_or_default();
+        let lines = diff::unified(
+            &format!("{} (on disk)", file_name),
+            &format!("{} (edited)", file_name),
+            &on_disk_lines,
+            &self.document.lines(),
+        );
+        if lines.is_empty() {
+            self.status_message = StatusMessage::from("No differences.".to_owned());
+            return;
+        }
+        let mut view = Document::view(&format!("{} [diff]", file_name), &lines);
+        view.set_file_type(FileType::diff());
+        self.open_view(view);
+        self.status_message =
+            StatusMessage::from("Enter = go to line | ESC = return to the file".to_owned());
+    }
+
+    /// Diff of the file shown under the current view, where its lines can be opened
+    fn is_diff_view(&self) -> bool {
+        self.document.is_read_only()
+            && self.document.file_type() == FileType::diff().name()
+            && !self.hidden_buffers.is_empty()
+    }
+
+    /// Close diff view and move cursor to the line which the diff line under cursor refers to
+    fn open_diff_line(&mut self) {
+        let line = diff::new_line_at(&self.document.lines(), self.cursor_position.y);
+        if let Some(line) = line {
+            self.close_view();
+            self.go_to(&Location {
+                line: LocationLine::Absolute(line.saturating_add(1)),
+                column: None,
+            });
+        } else {
+            self.status_message = StatusMessage::from("No line to go to.".to_owned());
+        }
+    }
+
+    /// Move cursor to the next hunk changed against the committed version of file
+    fn next_hunk(&mut self) {
+        let y = self.cursor_position.y;
+        let row = if let Some(compare) = self.active_compare() {
+            compare.next_hunk(y)
+        } else if let Some(changes) = self.document.git_changes() {
+            changes.next_hunk(y)
+        } else {
+            self.status_message = StatusMessage::from("File is not committed in git.".to_owned());
+            return;
+        };
+        self.jump_to_hunk(row);
+    }
+
+    fn previous_hunk(&mut self) {
+        let y = self.cursor_position.y;
+        let row = if let Some(compare) = self.active_compare() {
+            compare.previous_hunk(y)
+        } else if let Some(changes) = self.document.git_changes() {
+            changes.previous_hunk(y)
+        } else {
+            self.status_message = StatusMessage::from("File is not committed in git.".to_owned());
+            return;
+        };
+        self.jump_to_hunk(row);
+    }
+
+    /// Move cursor to hunk starting at `row`, which is `None` when there is no more hunk
+    fn jump_to_hunk(&mut self, row: Option<usize>) {
+        if let Some(y) = row {
+            let y = y.min(self.document.len().saturating_sub(1));
+            self.cursor_position = Position { x: 0, y };
+            self.scroll();
+        } else {
+            self.status_message = StatusMessage::from("No more changes.".to_owned());
+        }
+    }
+
+    /// Committed lines replaced by the hunk under cursor, with its first row and number of rows
+    fn hunk_under_cursor(&mut self) -> Option<(usize, usize, Vec<String>, String)> {
+        let changes = if let Some(changes) = self.document.git_changes() {
+            changes
+        } else {
+            self.status_message = StatusMessage::from("File is not committed in git.".to_owned());
+            return None;
+        };
+        if let Some(hunk) = changes.hunk_at(self.cursor_position.y) {
+            Some((
+                hunk.new_start,
+                hunk.new_len,
+                changes.original(hunk).to_vec(),
+                hunk.header(),
+            ))
+        } else {
+            self.status_message = StatusMessage::from("No change under cursor.".to_owned());
+            None
+        }
+    }
+
+    /// Replace rows of the hunk under cursor by their committed version
+    fn revert_hunk(&mut self) {
+        if self.document.is_read_only() {
+            self.status_message = StatusMessage::from("Buffer is read-only.".to_owned());
+            return;
+        }
+        if let Some((start, len, original, _)) = self.hunk_under_cursor() {
+            self.document.replace_rows(start, len, &original);
+            let y = start.min(self.document.len().saturating_sub(1));
+            self.cursor_position = Position { x: 0, y };
+            self.scroll();
+        }
+    }
+
+    /// Show committed version of the hunk under cursor in popup
+    fn show_hunk(&mut self) {
+        if let Some((_, _, original, header)) = self.hunk_under_cursor() {
+            let mut lines = vec![format!("HEAD {}", header)];
+            if original.is_empty() {
+                lines.push("(added lines)".to_owned());
+            }
+            lines.extend(original);
+            self.popup = Some(lines);
+        }
+    }
+
+    /// Show or hide the commit which last changed each row
+    fn toggle_blame(&mut self) {
+        if self.document.blame().is_some() {
+            self.document.set_blame(None);
+        } else {
+            self.update_blame();
+        }
+    }
+
+    /// Update blame when typing pauses, running git on every key would slow typing down
+    fn schedule_blame_update(&mut self) {
+        if let Some(timer) = self.blame_timer.take() {
+            self.timers.cancel(timer);
+        }
+        self.blame_timer = Some(
+            self.timers
+                .register(BLAME_UPDATE_DELAY, Self::update_outdated_blame),
+        );
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn update_outdated_blame(&mut self) -> io::Result<()> {
+        if let Some(timer) = self.blame_timer.take() {
+            self.timers.cancel(timer);
+        }
+        if self.document.is_blame_outdated() {
+            self.update_blame();
+        }
+        Ok(())
+    }
+
+    /// Annotate rows by commits which last changed them, blame is turned off if it fails
+    fn update_blame(&mut self) {
+        let blame = if self.document.is_hex() || self.document.is_read_only() {
+            None
+        } else {
+            self.document
+                .file_name
+                .as_ref()
+                .and_then(|file_name| git::blame(file_name, &self.document.lines().join("\n")))
+        };
+        if blame.is_none() {
+            self.status_message =
+                StatusMessage::from("ERR: Could not run git blame on this file.".to_owned());
+        }
+        self.document.set_blame(blame);
+    }
+
+    /// Show full message of the commit which last changed row under cursor
+    fn show_blame_commit(&mut self) {
+        let file_name = if let Some(ref file_name) = self.document.file_name {
+            file_name.clone()
+        } else {
+            return;
+        };
+        if self.document.blame().is_none() || self.document.is_blame_outdated() {
+            self.update_blame();
+        }
+        let commit = match self
+            .document
+            .blame()
+            .and_then(|blame| blame.get(self.cursor_position.y))
+        {
+            Some(Some(line)) if line.is_uncommitted() => {
+                self.status_message = StatusMessage::from("Line is not committed yet.".to_owned());
+                return;
+            }
+            Some(Some(line)) => line.commit.clone(),
+            Some(None) => {
+                self.status_message = StatusMessage::from("No commit for this line.".to_owned());
+                return;
+            }
+            None => return,
+        };
+        if let Some(lines) = git::commit_message(&file_name, &commit) {
+            let title = format!("commit {}", commit.get(..8).unwrap_or(&commit));
+            self.open_view(Document::view(&title, &lines));
+            self.status_message = StatusMessage::from("ESC = return to the file".to_owned());
+        } else {
+            self.status_message =
+                StatusMessage::from(format!("ERR: Could not show commit {}", commit));
+        }
+    }
+
+    /// Apply typing, deleting or movement `key` at every cursor
+    fn process_key_at_cursors(&mut self, key: Key) {
+        let (mut cursors, main) = self.cursors.all(&self.cursor_position);
+        if let Key::Char(_) | Key::Backspace | Key::Delete = key {
+            let pasting = self.terminal.is_pasting();
+            cursors::edit(
+                &mut self.document,
+                &mut cursors,
+                key,
+                self.tab_width,
+                pasting,
+            );
+        } else {
+            for cursor in &mut cursors {
+                self.cursor_position = cursor.clone();
+                self.move_cursor(key);
+                *cursor = self.cursor_position.clone();
+            }
+        }
+        self.cursors.set(cursors, main, &mut self.cursor_position);
+    }
+
+    /// Execute command `name` of multiple cursors. Returns `false` if it's another command.
+    fn cursor_command(&mut self, name: &str) -> bool {
+        let selection = self.selection_range();
+        match name {
+            "cursor-next-match" => self.add_cursor_at_next_match(selection),
+            "cursor-all-matches" => self.add_cursors_at_all_matches(selection),
+            "cursor-column" => self.add_cursors_in_selected_rows(selection),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Text searched for when cursors are added at matches: selection on one row, or word under cursor.
+    /// Without selection the main cursor moves to the end of the word.
+    fn cursor_query(&mut self, selection: Option<(Position, Position)>) -> Option<String> {
+        let query = match selection {
+            Some((start, end)) if start.y == end.y => self.document.text(&start, &end),
+            Some(_) => {
+                self.status_message =
+                    StatusMessage::from("Select text on one line to add cursors.".to_owned());
+                return None;
+            }
+            None => {
+                let word = self.word_under_cursor();
+                if self.cursors.is_empty() && !word.is_empty() {
+                    if let Some(row) = self.document.row(self.cursor_position.y) {
+                        let x = self.cursor_position.x;
+                        let (_, end) = row.word_at(x);
+                        let (_, previous_end) = row.word_at(x.saturating_sub(1));
+                        self.cursor_position.x = if end > x { end } else { previous_end };
+                    }
+                }
+                word
+            }
+        };
+        if query.is_empty() {
+            self.status_message = StatusMessage::from("Nothing to search for.".to_owned());
+            None
+        } else {
+            Some(query)
+        }
+    }
+
+    /// Add cursor at the end of the next match of selection or word under cursor
+    fn add_cursor_at_next_match(&mut self, selection: Option<(Position, Position)>) {
+        let query = if let Some(query) = self.cursor_query(selection) {
+            query
+        } else {
+            return;
+        };
+        let ends = cursors::match_ends(&self.document, &query, self.search_options);
+        if !self.cursors.add_next_match(&mut self.cursor_position, ends) {
+            self.status_message = StatusMessage::from(format!("No more matches of {}", query));
+        }
+    }
+
+    /// Add cursors at the ends of all matches of selection or word under cursor
+    fn add_cursors_at_all_matches(&mut self, selection: Option<(Position, Position)>) {
+        let query = if let Some(query) = self.cursor_query(selection) {
+            query
+        } else {
+            return;
+        };
+        let ends = cursors::match_ends(&self.document, &query, self.search_options);
+        if self
+            .cursors
+            .add_all_matches(&mut self.cursor_position, ends)
+        {
+            self.status_message = StatusMessage::from(format!("{} cursors", self.cursors.count()));
+        }
+    }
+
+    /// Add cursor on the row below the lowest cursor, or above the highest one, in the column of the main cursor
+    fn add_cursor_in_column(&mut self, down: bool) {
+        self.cursors
+            .add_in_column(&mut self.cursor_position, &self.document, down);
+    }
+
+    /// Add cursor on every selected row in the column of the main cursor
+    fn add_cursors_in_selected_rows(&mut self, selection: Option<(Position, Position)>) {
+        let (start, end) = if let Some(selection) = selection {
+            selection
+        } else {
+            self.status_message = StatusMessage::from("Select rows to add cursors to.".to_owned());
+            return;
+        };
+        self.selection = None;
+        self.cursors
+            .set_in_rows(&mut self.cursor_position, &self.document, start.y..=end.y);
+    }
+
+    /// Execute command `name` of block selection. Returns `false` if it's another command.
+    fn block_command(&mut self, name: &str, arguments: &str) -> bool {
+        match name {
+            "block" => self.toggle_block(),
+            "block-copy" => self.copy_block(false),
+            "block-cut" => self.copy_block(true),
+            "block-paste" => self.paste_block(),
+            "block-insert" => self.insert_in_block(arguments),
+            "block-fill" => self.fill_block(arguments),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Block selection between the anchor and the cursor
+    fn block(&self) -> Option<Block> {
+        let anchor = self.block_anchor.as_ref()?;
+        Some(Block::new(
+            &self.document,
+            anchor,
+            &self.cursor_position,
+            self.tab_width,
+        ))
+    }
+
+    /// Block selection which can be changed, the reason is shown when there's none
+    fn editable_block(&mut self) -> Option<Block> {
+        if self.document.is_read_only() {
+            self.status_message = StatusMessage::from("Buffer is read-only.".to_owned());
+            return None;
+        }
+        let block = self.block();
+        if block.is_none() {
+            self.status_message =
+                StatusMessage::from("No block selected, start one with Ctrl-B.".to_owned());
+        }
+        block
+    }
+
+    /// Start block selection at the cursor, or cancel it
+    fn toggle_block(&mut self) {
+        if self.document.is_hex() {
+            return;
+        }
+        if self.block_anchor.take().is_none() {
+            self.block_anchor = Some(self.cursor_position.clone());
+            self.status_message = StatusMessage::from(BLOCK_HELP.to_owned());
+        }
+    }
+
+    /// Copy block selection to the block clipboard, and delete it from document with `cut`
+    fn copy_block(&mut self, cut: bool) {
+        let block = if cut {
+            self.editable_block()
+        } else {
+            self.block()
+        };
+        let block = if let Some(block) = block {
+            block
+        } else {
+            return;
+        };
+        if block.is_empty() {
+            self.status_message = StatusMessage::from("Block is empty.".to_owned());
+            return;
+        }
+        self.block_clipboard = block.text(&self.document, self.tab_width);
+        if cut {
+            self.cursor_position = block.cut(&mut self.document, self.tab_width);
+        }
+        self.block_anchor = None;
+        self.status_message = StatusMessage::from(format!(
+            "{} block of {} lines.",
+            if cut { "Cut" } else { "Copied" },
+            block.height()
+        ));
+    }
+
+    /// Insert the copied block at the cursor column on the cursor row and rows below it
+    fn paste_block(&mut self) {
+        if self.document.is_read_only() {
+            self.status_message = StatusMessage::from("Buffer is read-only.".to_owned());
+            return;
+        }
+        if self.block_clipboard.is_empty() {
+            self.status_message = StatusMessage::from(
+                "Nothing to paste, copy a block with Ctrl-B and Ctrl-C first.".to_owned(),
+            );
+            return;
+        }
+        block::paste(
+            &mut self.document,
+            &self.cursor_position,
+            &self.block_clipboard,
+            self.tab_width,
+        );
+        self.block_anchor = None;
+    }
+
+    /// Insert `text` on every row of block selection before its left column, the block moves after the text
+    fn insert_in_block(&mut self, text: &str) {
+        if self.editable_block().is_none() {
+            return;
+        }
+        if let Some(anchor) = self.block_anchor.as_mut() {
+            block::insert(
+                &mut self.document,
+                anchor,
+                &mut self.cursor_position,
+                text,
+                self.tab_width,
+            );
+        }
+    }
+
+    /// Delete text of block selection. Empty block deletes the column before it with `backward`,
+    /// the one after it otherwise.
+    fn delete_in_block(&mut self, backward: bool) {
+        if self.editable_block().is_none() {
+            return;
+        }
+        if let Some(anchor) = self.block_anchor.as_mut() {
+            block::delete(
+                &mut self.document,
+                anchor,
+                &mut self.cursor_position,
+                backward,
+                self.tab_width,
+            );
+        }
+    }
+
+    /// Replace text of every row of block selection by `text` repeated to the width of block
+    fn fill_block(&mut self, text: &str) {
+        if let Some(block) = self.editable_block() {
+            block.fill(&mut self.document, text, self.tab_width);
+            self.block_anchor = None;
+        }
+    }
+
+    /// Execute command `name` of keyboard macros. Returns `false` if it's another command.
+    fn macro_command(&mut self, name: &str, arguments: &str) -> bool {
+        match name {
+            "record" => self.record(arguments),
+            "play" => self.play(arguments),
+            "bind" => self.bind_macro(arguments),
+            "save-macros" => self.save_macros(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Start recording keys into macro `name`, which is asked for when empty, or stop the recording
+    fn record(&mut self, name: &str) {
+        if let Some(recording) = self.recording.take() {
+            let (name, keys) = recording.finish();
+            self.status_message =
+                StatusMessage::from(format!("Recorded {} keys to macro {}.", keys.len(), name));
+            self.macros.registers.insert(name, keys);
+            return;
+        }
+        if name.is_empty() {
+            if let Some(name) = self.prompt("Record macro: ", |_, _, _| {}).unwrap_or(None) {
+                self.record(&name);
+            }
+            return;
+        }
+        if name.contains(char::is_whitespace) {
+            self.status_message = StatusMessage::from(format!("Invalid macro name: {}", name));
+            return;
+        }
+        self.recording = Some(Recording::new(name));
+        self.status_message =
+            StatusMessage::from(format!("Recording macro {}, Alt-R = stop recording", name));
+    }
+
+    /// Replay macro, `arguments` are `<name> [<count> | all]`. They are asked for when empty.
+    fn play(&mut self, arguments: &str) {
+        if arguments.is_empty() {
+            if let Some(arguments) = self
+                .prompt("Play macro (<name> [<count> | all]): ", |_, _, _| {})
+                .unwrap_or(None)
+            {
+                if !arguments.is_empty() {
+                    self.play(&arguments);
+                }
+            }
+            return;
+        }
+        match macros::parse_play_arguments(arguments) {
+            Ok((name, count)) => self.play_macro(name, count),
+            Err(count) => {
+                self.status_message = StatusMessage::from(format!("Invalid count: {}", count));
+            }
+        }
+    }
+
+    /// Replay macro `name` `count` times, or until it fails if `count` is `None`.
+    /// Replaying stops when a cursor movement fails, e.g. at the end of document.
+    fn play_macro(&mut self, name: &str, count: Option<usize>) {
+        let keys = if let Some(keys) = self.macros.registers.get(name) {
+            keys.clone()
+        } else {
+            self.status_message = StatusMessage::from(format!("Unknown macro: {}", name));
+            return;
+        };
+        if !self.replay.enter() {
+            self.status_message =
+                StatusMessage::from("ERR: Macros replay each other too deeply.".to_owned());
+            return;
+        }
+        let mut runs: usize = 0;
+        while runs < count.unwrap_or(MAX_MACRO_RUNS) && !self.should_quit {
+            let revision = self.document.revision();
+            let start = self.cursor_position.clone();
+            self.replay.start_run(&keys);
+            let mut failed = false;
+            while let Some(key) = self.replay.next_key() {
+                let before = self.cursor_position.clone();
+                self.process_key(key);
+                if macros::is_motion(key) && self.cursor_position == before {
+                    failed = true;
+                    break;
+                }
+            }
+            runs = runs.saturating_add(1);
+            let stuck = revision == self.document.revision() && start == self.cursor_position;
+            if failed || self.replay.is_aborted() || (count.is_none() && stuck) {
                 break;
             }
-            if let Err(error) = self.process_keypress() {
-                die(&error);
-            }
+        }
+        // Only the outermost macro reports how it went
+        if !self.replay.leave() {
+            return;
+        }
+        if self.replay.is_aborted() {
+            self.status_message = StatusMessage::from(format!(
+                "ERR: Macro {} aborted, it ended in a prompt.",
+                name
+            ));
+        } else {
+            self.status_message = StatusMessage::from(format!(
+                "Macro {} replayed {} time{}.",
+                name,
+                runs,
+                if runs == 1 { "" } else { "s" }
+            ));
         }
     }
 
-    pub fn default() -> Self {
-        let args: Vec<String> = env::args().collect();
-        let mut initial_status =
-            String::from("HELP: Ctrl-F = find | Ctrl-S = save | Ctrl-Q = quit");
-        let document = if let Some(file_name) = args.get(1) {
-            let doc = Document::open(file_name);
-            if let Ok(doc_unwrapped) = doc {
-                doc_unwrapped
-            } else {
-                initial_status = format!("ERR: Could not open file: {}", file_name);
-                Document::default()
-            }
+    /// Bind key to macro, `arguments` are `<key> [<name>]`. Without name the key is unbound.
+    fn bind_macro(&mut self, arguments: &str) {
+        let mut words = arguments.split_whitespace();
+        let key = if let Some(key) = words.next().and_then(macros::parse_key) {
+            key
         } else {
-            Document::default()
+            self.status_message = StatusMessage::from(format!("Invalid key: {}", arguments));
+            return;
+        };
+        if !macros::is_bindable(key) {
+            self.status_message =
+                StatusMessage::from("Keys typing text can't be bound to macros.".to_owned());
+            return;
+        }
+        let name = words.next();
+        if !self.macros.bind(key, name) {
+            self.status_message =
+                StatusMessage::from(format!("Unknown macro: {}", name.unwrap_or_default()));
+        }
+    }
+
+    /// Write macros and their key bindings to disk, they are loaded when editor starts
+    fn save_macros(&mut self) {
+        self.status_message = match self.macros.save() {
+            Ok(0) => StatusMessage::from(format!("Saved {} macros.", self.macros.registers.len())),
+            Ok(skipped) => StatusMessage::from(format!(
+                "Saved macros, {} with unsupported keys skipped.",
+                skipped
+            )),
+            Err(error) => StatusMessage::from(format!("ERR: Could not save macros: {}", error)),
         };
+    }
 
-        #[allow(clippy::expect_used)]
-        Self {
-            should_quit: false,
-            terminal: Terminal::default().expect("Failed to initialize terminal"),
-            cursor_position: Position::default(),
-            document,
-            offset: Position::default(),
-            status_message: StatusMessage::from(initial_status),
-            quit_times: QUIT_TIMES,
-            highlighted_word: None,
+    /// Move cursor to the start of the next merge conflict
+    fn next_conflict(&mut self) {
+        self.document.update_conflicts();
+        let y = self.cursor_position.y;
+        let start = self
+            .document
+            .conflicts()
+            .iter()
+            .map(|conflict| conflict.start)
+            .find(|&start| start > y);
+        self.jump_to_conflict(start);
+    }
+
+    fn previous_conflict(&mut self) {
+        self.document.update_conflicts();
+        let y = self.cursor_position.y;
+        let start = self
+            .document
+            .conflicts()
+            .iter()
+            .map(|conflict| conflict.start)
+            .rfind(|&start| start < y);
+        self.jump_to_conflict(start);
+    }
+
+    fn jump_to_conflict(&mut self, start: Option<usize>) {
+        if let Some(y) = start {
+            self.cursor_position = Position { x: 0, y };
+            self.scroll();
+        } else if self.document.conflicts().is_empty() {
+            self.status_message = StatusMessage::from("No merge conflicts.".to_owned());
+        } else {
+            self.status_message = StatusMessage::from("No more merge conflicts.".to_owned());
         }
     }
 
-    fn refresh_screen(&mut self) -> io::Result<()> {
-        Terminal::cursor_hide();
-        Terminal::set_cursor_position(&Position::default());
-        if self.should_quit {
-            Terminal::clear_screen();
-            println!("Goodbye.\r");
+    /// Resolve merge conflict under cursor by keeping `ours`, `theirs` or `both` sections
+    fn resolve_conflict(&mut self, arguments: &str) {
+        let resolution = match arguments {
+            "ours" => Resolution::Ours,
+            "theirs" => Resolution::Theirs,
+            "both" => Resolution::Both,
+            _ => {
+                self.status_message =
+                    StatusMessage::from(format!("Unknown resolution: {}", arguments));
+                return;
+            }
+        };
+        if self.document.is_read_only() {
+            self.status_message = StatusMessage::from("Buffer is read-only.".to_owned());
+            return;
+        }
+        self.document.update_conflicts();
+        let y = self.cursor_position.y;
+        let conflict = self
+            .document
+            .conflicts()
+            .iter()
+            .find(|conflict| conflict.contains(y))
+            .cloned();
+        if let Some(conflict) = conflict {
+            self.document.resolve_conflict(&conflict, resolution);
+            let y = conflict.start.min(self.document.len().saturating_sub(1));
+            self.cursor_position = Position { x: 0, y };
+            self.scroll();
+            self.document.update_conflicts();
+            let left = self.document.conflicts().len();
+            self.status_message = StatusMessage::from(format!("Conflict resolved, {} left.", left));
         } else {
-            self.document.highlight(
-                &self.highlighted_word,
-                Some(
-                    self.offset
-                        .y
-                        .saturating_add(self.terminal.size().height as usize),
-                ),
-            );
-            self.draw_rows();
-            self.draw_status_bar();
-            self.draw_message_bar();
-            Terminal::set_cursor_position(&Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
-                y: self.cursor_position.y.saturating_sub(self.offset.y),
-            });
+            self.status_message = StatusMessage::from("No merge conflict under cursor.".to_owned());
         }
-        Terminal::cursor_show();
-        Terminal::flush()
     }
 
-    pub fn draw_row(&self, row: &Row) {
-        let width = self.terminal.size().width as usize;
-        let start = self.offset.x;
-        let end = self.offset.x.saturating_add(width);
-        let row = row.render(start, end);
-        println!("{}\r", row);
+    /// Execute command `name` of side-by-side comparison. Returns `false` if it's another command.
+    fn compare_command(&mut self, name: &str) -> bool {
+        match name {
+            "put-hunk" => self.copy_hunk(true),
+            "get-hunk" => self.copy_hunk(false),
+            "close-compare" => self.close_compare(),
+            _ => return false,
+        }
+        true
     }
 
-    #[allow(clippy::integer_division)]
-    fn draw_rows(&self) {
-        let height = self.terminal.size().height;
-        for terminal_row in 0..height {
-            Terminal::clear_current_line();
-            if let Some(row) = self
-                .document
-                .row(self.offset.y.saturating_add(terminal_row as usize))
-            {
-                self.draw_row(row);
-            } else if self.document.is_empty() && terminal_row == height / 3 {
-                self.draw_welcome_message();
-            } else {
-                println!("~\r");
+    /// Compare the current document side by side with file `file_name`, which is shown on the right
+    fn compare_with(&mut self, file_name: &str) {
+        match open_document(file_name) {
+            Ok(document) => {
+                self.compare = Some(Compare::new(document));
+                self.update_comparison();
+                self.status_message = StatusMessage::from(COMPARE_HELP.to_owned());
+            }
+            Err(_) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: Could not open file: {}", file_name));
             }
         }
     }
 
-    fn draw_welcome_message(&self) {
-        let mut welcome_message = format!("Hecto editor -- version {}", VERSION);
-        let width = self.terminal.size().width as usize;
-        let len = welcome_message.len();
-        #[allow(clippy::integer_division)]
-        let padding = width.saturating_sub(len.saturating_add(1)) / 2;
-        let spaces = " ".repeat(padding);
-        welcome_message = format!("~{}{}", spaces, welcome_message);
-        welcome_message.truncate(width);
-        println!("{}\r", welcome_message);
+    /// Stop comparing files, the current document stays open
+    fn close_compare(&mut self) {
+        if self.compare.is_none() {
+            self.status_message = StatusMessage::from("Not comparing files.".to_owned());
+            return;
+        }
+        if self.confirm_discard_compared() {
+            self.compare = None;
+            self.status_message = StatusMessage::from("Comparison closed.".to_owned());
+        }
     }
 
-    fn process_keypress(&mut self) -> io::Result<()> {
-        let pressed_key = Terminal::read_key()?;
-        match pressed_key {
-            Key::Ctrl('q') => {
-                #[allow(clippy::integer_arithmetic)]
-                if self.quit_times > 0 && self.document.is_dirty() {
-                    self.status_message = StatusMessage::from(format!(
-                        "WARNING! File has unsaved changes. Press Ctrl-Q {} more times to quit.",
-                        self.quit_times
-                    ));
-                    self.quit_times -= 1;
-                    return Ok(());
+    /// Ask user whether unsaved changes of the other compared file may be discarded. Returns `true` if there are no changes.
+    fn confirm_discard_compared(&mut self) -> bool {
+        let other_dirty = self
+            .compare
+            .as_ref()
+            .is_some_and(|compare| compare.other().is_dirty());
+        !other_dirty
+            || self
+                .choose(
+                    "Compared file has unsaved changes. Discard them? (y/n)",
+                    &['y', 'n'],
+                )
+                .unwrap_or(None)
+                == Some('y')
+    }
+
+    /// Side-by-side diff, unless another document is shown over the compared ones
+    fn active_compare(&self) -> Option<&Compare> {
+        self.compare
+            .as_ref()
+            .filter(|_| self.hidden_buffers.is_empty())
+    }
+
+    /// Align lines of compared files again if they changed
+    fn update_comparison(&mut self) {
+        if let Some(ref mut compare) = self.compare {
+            compare.update(&self.document);
+        }
+    }
+
+    /// Edit the other compared file, cursor moves to the line aligned with the current one
+    fn switch_side(&mut self) {
+        if self.active_compare().is_none() {
+            self.status_message = StatusMessage::from("Not comparing files.".to_owned());
+            return;
+        }
+        if let Some(ref mut compare) = self.compare {
+            compare.switch_side(
+                &mut self.document,
+                &mut self.cursor_position,
+                &mut self.offset,
+            );
+        }
+        self.scroll();
+    }
+
+    /// Copy hunk under cursor to the other compared file, or replace it by the other file's version
+    fn copy_hunk(&mut self, to_other: bool) {
+        if self.active_compare().is_none() {
+            self.status_message = StatusMessage::from("Not comparing files.".to_owned());
+            return;
+        }
+        let y = self.cursor_position.y;
+        let copied = match self.compare {
+            Some(ref mut compare) if to_other => compare.put_hunk(&self.document, y),
+            Some(ref compare) => {
+                if let Some(start) = compare.get_hunk(&mut self.document, y) {
+                    let y = start.min(self.document.len().saturating_sub(1));
+                    self.cursor_position = Position { x: 0, y };
+                    true
+                } else {
+                    false
                 }
-                self.should_quit = true;
             }
-            Key::Ctrl('s') => self.save(),
-            Key::Ctrl('f') => self.search(),
-            Key::Char(c) => {
-                self.document.insert(&self.cursor_position, c);
-                if let Some(row) = self.document.row(self.cursor_position.y) {
-                    let mut move_cursor = false;
-                    if let Some(grapheme) =
-                        row.get_string().graphemes(true).nth(self.cursor_position.x)
-                    {
-                        // Move cursor for single unicode characters and do not move cursor in case of finished emoji flag sequence, e.g. 🇨🇿 (two unicode characters in one)
-                        if grapheme == c.to_string() {
-                            move_cursor = true;
+            None => false,
+        };
+        if copied {
+            self.scroll();
+        } else {
+            self.status_message = StatusMessage::from("No change under cursor.".to_owned());
+        }
+    }
+
+    /// Show `document` over the current one, which is restored by `close_view`
+    fn open_view(&mut self, document: Document) {
+        let previous = Buffer {
+            document: std::mem::replace(&mut self.document, document),
+            cursor_position: std::mem::take(&mut self.cursor_position),
+            offset: std::mem::take(&mut self.offset),
+        };
+        self.hidden_buffers.push(previous);
+    }
+
+    fn close_view(&mut self) {
+        if let Some(buffer) = self.hidden_buffers.pop() {
+            self.document = buffer.document;
+            self.cursor_position = buffer.cursor_position;
+            self.offset = buffer.offset;
+        }
+    }
+
+    /// Incremental search. Cursor moves to matches while typing, ESC returns it where search started.
+    fn search(&mut self) {
+        let start_position = self.cursor_position.clone();
+        let start_offset = self.offset.clone();
+        let mut direction = SearchDirection::Forward;
+        self.prompt_hint = Some(self.search_options.describe());
+        let query = self
+            .prompt(
+                "Search (Ctrl-N/P next/previous, Alt-C/S/W/R options): ",
+                |editor, key, query| {
+                    let mut moved = false;
+                    let mut from = editor.cursor_position.clone();
+                    match key {
+                        Key::Ctrl('n') => {
+                            direction = SearchDirection::Forward;
+                            editor.move_cursor(Key::Right);
+                            from = editor.cursor_position.clone();
+                            moved = true;
+                        }
+                        Key::Ctrl('p') => direction = SearchDirection::Backward,
+                        // Cursor movement inside the prompt doesn't change the query
+                        Key::Left
+                        | Key::Right
+                        | Key::Home
+                        | Key::End
+                        | Key::CtrlLeft
+                        | Key::CtrlRight
+                        | Key::Ctrl('a' | 'e')
+                        | Key::Alt('b' | 'f') => return,
+                        Key::Ctrl('f') if query.is_empty() => {
+                            *query = editor.word_under_cursor();
+                            from = start_position.clone();
+                        }
+                        Key::Alt('c') => {
+                            editor.search_options.ignore_case = !editor.search_options.ignore_case;
+                        }
+                        Key::Alt('s') => {
+                            editor.search_options.smart_case = !editor.search_options.smart_case;
+                        }
+                        Key::Alt('w') => {
+                            editor.search_options.whole_word = !editor.search_options.whole_word;
+                        }
+                        Key::Alt('r') => {
+                            editor.search_options.wrap_around = !editor.search_options.wrap_around;
+                        }
+                        // Changed query is searched again from where search started
+                        _ => {
+                            direction = SearchDirection::Forward;
+                            from = start_position.clone();
                         }
-                    } else {
-                        // ENTER is pressed
-                        move_cursor = true;
                     }
-                    if move_cursor {
-                        self.move_cursor(Key::Right);
+                    let options = editor.search_options;
+                    if let Some(position) = editor.document.find(query, &from, direction, options) {
+                        editor.cursor_position = position;
+                        editor.scroll();
+                    } else if moved {
+                        editor.move_cursor(Key::Left);
+                    } else if query.is_empty() {
+                        editor.cursor_position = start_position.clone();
+                        editor.offset = start_offset.clone();
                     }
-                }
-            }
-            Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
-                    self.document.delete(&self.cursor_position);
-                }
-            }
-            Key::Up
-            | Key::Down
-            | Key::Left
-            | Key::Right
-            | Key::PageUp
-            | Key::PageDown
-            | Key::End
-            | Key::Home => self.move_cursor(pressed_key),
-            _ => {}
-        }
-        self.scroll();
-        if self.quit_times < QUIT_TIMES {
-            self.quit_times = QUIT_TIMES;
-            self.status_message = StatusMessage::from(String::new());
+                    editor.highlighted_word = Some(query.clone());
+                    editor.prompt_hint = Some(format!(
+                        "{}{}",
+                        editor.match_count(query),
+                        options.describe()
+                    ));
+                },
+            )
+            .unwrap_or(None);
+        if query.is_none() {
+            self.cursor_position = start_position;
+            self.offset = start_offset;
         }
-        Ok(())
+        self.highlighted_word = None;
+        self.document.reset_highlighting();
     }
 
-    fn save(&mut self) {
-        if self.document.file_name.is_none() {
-            let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None);
-            if new_name.is_none() {
-                self.status_message = StatusMessage::from("Save aborted.".to_owned());
-                return;
-            }
-            self.document.file_name = new_name;
+    /// Word at cursor position or just before it, empty when cursor isn't at a word
+    fn word_under_cursor(&self) -> String {
+        self.document.word_at(&self.cursor_position)
+    }
+
+    /// Position of match under cursor among all matches of `query`, e.g. `  match 3 of 17`
+    fn match_count(&self, query: &str) -> String {
+        if query.is_empty() {
+            return String::new();
+        }
+        let matches = self.document.find_all(query, self.search_options);
+        if matches.is_empty() {
+            return "  no matches".to_owned();
         }
+        match matches
+            .iter()
+            .position(|position| *position == self.cursor_position)
+        {
+            Some(index) => format!("  match {} of {}", index.saturating_add(1), matches.len()),
+            None => format!("  {} matches", matches.len()),
+        }
+    }
 
-        if self.document.save().is_ok() {
-            self.status_message = StatusMessage::from("File saved successfully.".to_owned());
+    /// Overwrite or insert one nibble of byte under cursor in hex view
+    fn hex_input(&mut self, c: char) {
+        let value = if let Some(digit) = c.to_digit(16).and_then(|digit| u8::try_from(digit).ok()) {
+            digit
         } else {
-            self.status_message = StatusMessage::from("Error writing file!".to_owned());
+            self.status_message =
+                StatusMessage::from(format!("'{}' is not a hexadecimal digit.", c));
+            return;
+        };
+        let old = self.document.hex_byte(&self.cursor_position);
+        if self.hex_nibble {
+            let high = old.unwrap_or(0) & 0xf0;
+            self.document
+                .hex_set_byte(&self.cursor_position, high | value);
+            self.move_cursor(Key::Right);
+        } else {
+            if self.hex_insert || old.is_none() {
+                self.document
+                    .hex_insert_byte(&self.cursor_position, value << 4_u8);
+            } else {
+                let low = old.unwrap_or(0) & 0x0f;
+                self.document
+                    .hex_set_byte(&self.cursor_position, (value << 4_u8) | low);
+            }
+            self.hex_nibble = true;
         }
     }
 
-    fn search(&mut self) {
+    fn hex_search(&mut self) {
         let mut direction = SearchDirection::Forward;
         self.prompt(
-            "Search (ESC to cancel, Arrows to navigate): ",
+            "Search bytes, e.g. 7f 45 4c 46 or text (Ctrl-N/P next/previous): ",
             |editor, key, query| {
                 let mut moved = false;
                 match key {
-                    Key::Right | Key::Down => {
+                    Key::Ctrl('n') => {
                         direction = SearchDirection::Forward;
                         editor.move_cursor(Key::Right);
                         moved = true;
                     }
-                    Key::Left | Key::Up => direction = SearchDirection::Backward,
+                    Key::Ctrl('p') => direction = SearchDirection::Backward,
                     _ => direction = SearchDirection::Forward,
                 }
-                if let Some(position) =
-                    editor
-                        .document
-                        .find(query, &editor.cursor_position, direction)
-                {
+                if let Some(position) = editor.document.hex_find(
+                    &parse_hex_pattern(query),
+                    &editor.cursor_position,
+                    direction,
+                ) {
                     editor.cursor_position = position;
                     editor.scroll();
                 } else if moved {
                     editor.move_cursor(Key::Left);
                 }
-                editor.highlighted_word = Some(query.to_string());
             },
         )
         .unwrap_or(None);
-        // Disable highlighting of found results by clearing of query
-        self.document.highlight(
-            &Some("".to_owned()),
-            Some(
-                self.offset
-                    .y
-                    .saturating_add(self.terminal.size().height as usize),
-            ),
-        );
-        self.highlighted_word = None;
     }
 
     fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
-        let width = self.terminal.size().width as usize;
+        let y = self.cursor_position.y;
+        let x = self
+            .document
+            .render_x(&self.cursor_position, self.tab_width);
+        let width = self.text_width();
         let height = self.terminal.size().height as usize;
+        self.update_comparison();
         let mut offset = &mut self.offset;
         if y < offset.y {
             offset.y = y;
@@ -299,9 +2834,17 @@ impl Editor {
         } else if x >= offset.x.saturating_add(width) {
             offset.x = x.saturating_sub(width).saturating_add(1);
         }
+        // Filler rows of side-by-side diff take screen rows too
+        if let Some(compare) = self.active_compare() {
+            if self.screen_row(y) >= height {
+                self.offset.y =
+                    compare.line_at(compare.row_of(y).saturating_add(1).saturating_sub(height));
+            }
+        }
     }
 
     fn move_cursor(&mut self, key: Key) {
+        self.hex_nibble = false;
         let terminal_height = self.terminal.size().height as usize;
         let Position { mut y, mut x } = self.cursor_position;
         let last_line_pos = self.document.len().saturating_sub(1);
@@ -342,6 +2885,22 @@ impl Editor {
             Key::PageDown => y = cmp::min(y.saturating_add(terminal_height), last_line_pos),
             Key::Home => x = 0,
             Key::End => x = width,
+            Key::CtrlLeft if !self.document.is_hex() => {
+                Position { x, y } = self.document.previous_word_start(&self.cursor_position);
+            }
+            Key::CtrlRight if !self.document.is_hex() => {
+                Position { x, y } = self.document.next_word_end(&self.cursor_position);
+            }
+            Key::CtrlUp => {
+                y = self.document.previous_paragraph(y);
+                x = 0;
+            }
+            Key::CtrlDown => {
+                y = self.document.next_paragraph(y);
+                x = 0;
+            }
+            Key::CtrlHome => Position { x, y } = Position::default(),
+            Key::CtrlEnd => Position { x, y } = self.document.end(),
             _ => {}
         };
 
@@ -357,7 +2916,9 @@ impl Editor {
     fn draw_status_bar(&self) {
         let width = self.terminal.size().width as usize;
 
-        let modified_indicator = if self.document.is_dirty() {
+        let modified_indicator = if self.document.is_read_only() {
+            " (read-only)"
+        } else if self.document.is_dirty() {
             " (modified)"
         } else {
             ""
@@ -369,18 +2930,63 @@ impl Editor {
             file_name.truncate(20);
         }
 
-        let mut status = format!(
-            "{} - {} lines{}",
-            file_name,
-            self.document.len(),
-            modified_indicator
-        );
-        let line_indicator = format!(
-            "Ln {}, Col {}, {}",
-            self.cursor_position.y.saturating_add(1),
-            self.cursor_position.x.saturating_add(1),
-            self.document.file_type()
-        );
+        let mut status;
+        let line_indicator;
+        if self.document.is_hex() {
+            status = format!(
+                "{} - {} bytes{}",
+                file_name,
+                self.document.hex_len(),
+                modified_indicator
+            );
+            let offset = Document::byte_offset(&self.cursor_position);
+            line_indicator = format!(
+                "{} Offset 0x{:08x} ({}), {}",
+                if self.hex_insert { "INS" } else { "OVR" },
+                offset,
+                offset,
+                self.document.file_type()
+            );
+        } else {
+            status = format!(
+                "{} - {} lines{}",
+                file_name,
+                self.document.len(),
+                modified_indicator
+            );
+            if !self.cursors.is_empty() {
+                status.push_str(&format!(" - {} cursors", self.cursors.count()));
+            }
+            if let Some(block) = self.block() {
+                status.push_str(&format!(" - block {}x{}", block.height(), block.width()));
+            }
+            if let Some(ref recording) = self.recording {
+                status.push_str(&format!(" - recording {}", recording.name()));
+            }
+            let conflicts = self.document.conflicts().len();
+            if conflicts > 0 {
+                status.push_str(&format!(
+                    " - {} conflict{}",
+                    conflicts,
+                    if conflicts == 1 { "" } else { "s" }
+                ));
+            }
+            // Indentation matters only in text, not in hex view or lists of files and locations
+            let is_text = !self.document.is_hex()
+                && self.document.listing().is_none()
+                && !self.document.is_location_list();
+            line_indicator = format!(
+                "Ln {}, Col {}, {}{}",
+                self.cursor_position.y.saturating_add(1),
+                self.cursor_position.x.saturating_add(1),
+                if is_text {
+                    format!("{}, ", self.document.indentation_style().name())
+                } else {
+                    String::new()
+                },
+                self.document.file_type()
+            );
+        }
         #[allow(clippy::integer_arithmetic)]
         let len = status.len() + line_indicator.len();
         status.push_str(&" ".repeat(width.saturating_sub(len)));
@@ -397,49 +3003,254 @@ impl Editor {
     fn draw_message_bar(&self) {
         Terminal::clear_current_line();
         let message = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
-            let mut text = message.text.clone();
-            text.truncate(self.terminal.size().width as usize);
+        if self.prompt_active || message.time.elapsed() < STATUS_MESSAGE_DURATION {
+            let mut width: usize = 0;
+            let text: String = message
+                .text
+                .graphemes(true)
+                .take_while(|grapheme| {
+                    width = width.saturating_add(grapheme.width());
+                    width <= self.terminal.size().width as usize
+                })
+                .collect();
             print!("{}", text);
         }
     }
 
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error>
+    /// Show `question` in message bar and wait until one of `choices` is pressed. ESC cancels the question.
+    fn choose(&mut self, question: &str, choices: &[char]) -> io::Result<Option<char>> {
+        self.status_message = StatusMessage::from(question.to_owned());
+        self.prompt_active = true;
+        let choice = loop {
+            self.refresh_screen()?;
+            match self.read_key()? {
+                Key::Char(c) if choices.contains(&c.to_ascii_lowercase()) => {
+                    break Some(c.to_ascii_lowercase())
+                }
+                Key::Esc => break None,
+                _ => (),
+            }
+        };
+        self.prompt_active = false;
+        self.status_message = StatusMessage::from(String::new());
+        Ok(choice)
+    }
+
+    /// Read a line in message bar, `None` when it's empty or the prompt is cancelled
+    fn prompt<C>(&mut self, prompt: &str, callback: C) -> Result<Option<String>, std::io::Error>
     where
-        C: FnMut(&mut Self, Key, &String),
+        C: FnMut(&mut Self, Key, &mut String),
     {
-        let mut result = String::new();
-        loop {
-            self.status_message = StatusMessage::from(format!("{}{}", prompt, result));
+        let (accepted, line) = self.read_prompt(prompt, callback)?;
+        Ok((accepted && !line.is_empty()).then_some(line))
+    }
+
+    /// Read a line in message bar. Line can be edited like in readline and Up/Down browse answers
+    /// of the same prompt. `callback` is called after each key press except Enter and ESC.
+    /// Returns whether the line was accepted by Enter, and the line.
+    fn read_prompt<C>(
+        &mut self,
+        prompt: &str,
+        mut callback: C,
+    ) -> Result<(bool, String), std::io::Error>
+    where
+        C: FnMut(&mut Self, Key, &mut String),
+    {
+        let mut line = Line::default();
+        line.killed = mem::take(&mut self.prompt_killed);
+        let history = self.prompt_history.get(prompt).cloned().unwrap_or_default();
+        let mut history_index = history.len();
+        let mut draft = String::new();
+        let mut scroll = 0;
+        self.prompt_active = true;
+        let accepted = loop {
+            let hint = self.prompt_hint.clone().unwrap_or_default();
+            let prompt_width = prompt.width();
+            let input_width = (self.terminal.size().width as usize).saturating_sub(prompt_width);
+            let visible = line.visible(&mut scroll, input_width);
+            self.prompt_cursor = Some(prompt_width.saturating_add(line.cursor_column(scroll)));
+            self.status_message = StatusMessage::from(format!("{}{}{}", prompt, visible, hint));
             self.refresh_screen()?;
-            let key = Terminal::read_key()?;
+            let key = self.read_key()?;
             match key {
-                Key::Backspace => {
-                    let graphemes_cnt = result.graphemes(true).count();
-                    result = result
-                        .graphemes(true)
-                        .take(graphemes_cnt.saturating_sub(1))
-                        .collect();
-                }
-                Key::Char('\n') => break,
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
+                Key::Char('\n') => break true,
+                Key::Esc => break false,
+                Key::Up | Key::Down => {
+                    if history_index == history.len() {
+                        line.text().clone_into(&mut draft);
                     }
+                    history_index = if key == Key::Up {
+                        history_index.saturating_sub(1)
+                    } else {
+                        history_index.saturating_add(1).min(history.len())
+                    };
+                    line.set_text(
+                        history
+                            .get(history_index)
+                            .cloned()
+                            .unwrap_or_else(|| draft.clone()),
+                    );
                 }
-                Key::Esc => {
-                    result.truncate(0);
-                    break;
+                _ => {
+                    line.process_key(key);
                 }
-                _ => (),
             }
-            callback(self, key, &result);
-        }
+            let mut text = line.text().to_owned();
+            callback(self, key, &mut text);
+            if text != line.text() {
+                line.set_text(text);
+            }
+        };
+        self.prompt_active = false;
+        self.prompt_hint = None;
+        self.prompt_cursor = None;
+        self.prompt_killed = mem::take(&mut line.killed);
         self.status_message = StatusMessage::from(String::new());
-        if result.is_empty() {
-            return Ok(None);
+        let result = line.text().to_owned();
+        if accepted && !result.is_empty() {
+            let history = self.prompt_history.entry(prompt.to_owned()).or_default();
+            history.retain(|previous| *previous != result);
+            history.push(result.clone());
+        }
+        Ok((accepted, result))
+    }
+}
+
+/// Parse search query of hex view. Hexadecimal digits optionally separated by whitespace are taken as bytes,
+/// anything else is searched as text.
+fn parse_hex_pattern(query: &str) -> Vec<u8> {
+    let digits: Vec<u8> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map_while(|c| c.to_digit(16).and_then(|digit| u8::try_from(digit).ok()))
+        .collect();
+    let non_whitespace = query.chars().filter(|c| !c.is_whitespace()).count();
+    if digits.is_empty() || digits.len() != non_whitespace || digits.len().checked_rem(2) != Some(0)
+    {
+        return query.as_bytes().to_vec();
+    }
+    digits
+        .chunks(2)
+        .map(|pair| pair.iter().fold(0, |byte, digit| (byte << 4_u8) | digit))
+        .collect()
+}
+
+/// Split command line arguments into file name and location in it.
+/// Both `hecto file:line:col` and `hecto +line file` are accepted.
+fn parse_arguments(args: &[String]) -> (Option<&str>, Option<Location>) {
+    let mut file_name = None;
+    let mut location = None;
+    for arg in args {
+        if let Some(line) = arg.strip_prefix('+') {
+            if let Some(parsed) = Location::parse(line) {
+                location = Some(parsed);
+                continue;
+            }
+        }
+        let (name, parsed) = location::split_file_location(arg);
+        file_name = Some(name);
+        location = parsed.or(location);
+    }
+    (file_name, location)
+}
+
+/// Open file as text, or in hex view if it's binary. Directory is shown in the file browser.
+fn open_document(file_name: &str) -> io::Result<Document> {
+    if Path::new(file_name).is_dir() {
+        return Document::open_directory(Path::new(file_name));
+    }
+    match Document::open(file_name) {
+        Err(error) if error.kind() == io::ErrorKind::InvalidData => Document::open_hex(file_name),
+        Ok(mut document) => {
+            document.set_git_base(git::head_lines(file_name));
+            Ok(document)
+        }
+        result => result,
+    }
+}
+
+/// Blame column of a row: short commit hash, author and date
+fn blame_annotation(line: Option<&BlameLine>) -> String {
+    let text = match line {
+        Some(line) if line.is_uncommitted() => "Not committed yet".to_owned(),
+        Some(line) => {
+            let author: String = line.author.chars().take(10).collect();
+            let date: String = browser::format_time(line.time).chars().take(10).collect();
+            format!(
+                "{} {:10} {}",
+                line.commit.get(..8).unwrap_or(&line.commit),
+                author,
+                date
+            )
+        }
+        None => String::new(),
+    };
+    format!("{:width$} ", text, width = BLAME_WIDTH.saturating_sub(1))
+}
+
+/// Indentation style written as `tabs` or width of spaces, e.g. `4`
+fn parse_indentation(value: &str) -> Option<Indentation> {
+    match value.trim() {
+        "tabs" => Some(Indentation::Tabs),
+        width => width
+            .parse()
+            .ok()
+            .filter(|width| (1..=16).contains(width))
+            .map(Indentation::Spaces),
+    }
+}
+
+/// Help shown in message bar when `document` is opened
+fn document_help(document: &Document) -> Option<&'static str> {
+    if document.is_hex() {
+        Some(HEX_HELP)
+    } else if document.listing().is_some() {
+        Some(BROWSER_HELP)
+    } else {
+        None
+    }
+}
+
+/// Prompt callback completing file system path on Tab
+fn complete_file_name(editor: &mut Editor, key: Key, path: &mut String) {
+    editor.prompt_hint = None;
+    if key == Key::Char('\t') {
+        let (completed, names) = prompt::complete_path(path);
+        *path = completed;
+        editor.prompt_hint = Some(completion_hint(&names));
+    }
+}
+
+/// Candidates for completion shown after prompt, none when completion is unambiguous
+fn completion_hint(names: &[String]) -> String {
+    match names.len() {
+        0 => "  (no match)".to_owned(),
+        1 => String::new(),
+        _ => format!("  [{}]", names.join(" | ")),
+    }
+}
+
+/// Hint shown after command line: matching commands while the name is typed, then arguments of the command
+fn command_hint(line: &str) -> String {
+    let (name, arguments) = command::split(line);
+    let typing_name = !line.trim_start().contains(char::is_whitespace);
+    if typing_name {
+        let names: Vec<&str> = command::matching(name)
+            .iter()
+            .take(COMMAND_SUGGESTIONS)
+            .map(|command| command.name)
+            .collect();
+        if names.is_empty() {
+            return "  (no matching command)".to_owned();
+        }
+        return format!("  [{}]", names.join(" | "));
+    }
+    match command::find(name) {
+        Some(command) if arguments.is_empty() => {
+            format!("  {} -- {}", command.arguments, command.description)
         }
-        Ok(Some(result))
+        Some(_) => String::new(),
+        None => "  (unknown command)".to_owned(),
     }
 }
 
@@ -448,3 +3259,17 @@ fn die(e: &std::io::Error) {
     Terminal::clear_screen();
     panic!("{}", e);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_indentation() {
+        assert!(parse_indentation("tabs") == Some(Indentation::Tabs));
+        assert!(parse_indentation(" 2 ") == Some(Indentation::Spaces(2)));
+        assert!(parse_indentation("0").is_none());
+        assert!(parse_indentation("17").is_none());
+        assert!(parse_indentation("spaces").is_none());
+    }
+}
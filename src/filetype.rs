@@ -26,6 +26,8 @@ pub struct HighlightingOptions {
     characters: bool,
     comments: bool,
     multiline_comments: bool,
+    /// Whole lines are highlighted by their prefix in unified diff format
+    diff: bool,
     primary_keywords: Vec<(String, usize)>,
     secondary_keywords: Vec<(String, usize)>,
 }
@@ -46,6 +48,9 @@ impl HighlightingOptions {
     pub fn multiline_comments(&self) -> bool {
         self.multiline_comments
     }
+    pub fn diff(&self) -> bool {
+        self.diff
+    }
     pub fn primary_keywords(&self) -> &Vec<(String, usize)> {
         &self.primary_keywords
     }
@@ -54,9 +59,28 @@ impl HighlightingOptions {
     }
 }
 
+/// How lines are indented while typing. New line always starts with indentation of the previous one.
+#[derive(Default)]
+pub struct IndentationRules {
+    /// Characters at the end of line which indent the next line one more level
+    indent_after: Vec<char>,
+    /// Characters which get indentation of the line with the opening one when typed at the start of line
+    dedent_on: Vec<char>,
+}
+
+impl IndentationRules {
+    pub fn indents_after(&self, c: char) -> bool {
+        self.indent_after.contains(&c)
+    }
+    pub fn dedents_on(&self, c: char) -> bool {
+        self.dedent_on.contains(&c)
+    }
+}
+
 pub struct FileType {
     name: String,
     hl_opts: HighlightingOptions,
+    indentation: IndentationRules,
 }
 
 impl FileType {
@@ -68,38 +92,83 @@ impl FileType {
         &self.hl_opts
     }
 
+    pub fn indentation_rules(&self) -> &IndentationRules {
+        &self.indentation
+    }
+
+    /// File type of binary files shown in hex view
+    pub fn hex() -> Self {
+        Self {
+            name: String::from("Hex"),
+            hl_opts: HighlightingOptions::default(),
+            indentation: IndentationRules::default(),
+        }
+    }
+
+    /// File type of changes in unified diff format
+    pub fn diff() -> Self {
+        Self {
+            name: String::from("Diff"),
+            hl_opts: HighlightingOptions {
+                diff: true,
+                ..HighlightingOptions::default()
+            },
+            indentation: IndentationRules::default(),
+        }
+    }
+
     pub fn from(file_name: &str) -> Self {
-        if file_name
-            .rsplit('.')
-            .next()
-            .map(|ext| ext.eq_ignore_ascii_case("rs"))
-            == Some(true)
-        {
-            return Self {
-                name: String::from("Rust"),
-                hl_opts: HighlightingOptions {
-                    numbers: true,
-                    strings: true,
-                    characters: true,
-                    comments: true,
-                    multiline_comments: true,
-                    primary_keywords: generate_keywords_len(&[
-                        "as", "break", "const", "continue", "crate", "else", "enum", "extern",
-                        "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
-                        "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct",
-                        "super", "trait", "true", "type", "unsafe", "use", "where", "while", "dyn",
-                        "abstract", "become", "box", "do", "final", "macro", "override", "priv",
-                        "typeof", "unsized", "virtual", "yield", "async", "await", "try",
-                    ]),
-                    secondary_keywords: generate_keywords_len(&[
-                        "bool", "char", "i8", "i16", "i32", "i64", "isize", "u8", "u16", "u32",
-                        "u64", "usize", "f32", "f64",
-                    ]),
-                },
-            };
+        let extension = file_name.rsplit('.').next();
+        if extension.is_some_and(|ext| ext.eq_ignore_ascii_case("rs")) {
+            return Self::rust();
+        }
+        if extension.is_some_and(|ext| {
+            ext.eq_ignore_ascii_case("diff") || ext.eq_ignore_ascii_case("patch")
+        }) {
+            return Self::diff();
         }
         Self::default()
     }
+
+    /// File type by its case-insensitive name, e.g. `rust`. `none` is plain text.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "rust" | "rs" => Some(Self::rust()),
+            "diff" | "patch" => Some(Self::diff()),
+            "none" | "text" => Some(Self::default()),
+            _ => None,
+        }
+    }
+
+    fn rust() -> Self {
+        Self {
+            name: String::from("Rust"),
+            hl_opts: HighlightingOptions {
+                numbers: true,
+                strings: true,
+                characters: true,
+                comments: true,
+                multiline_comments: true,
+                diff: false,
+                primary_keywords: generate_keywords_len(&[
+                    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false",
+                    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+                    "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+                    "true", "type", "unsafe", "use", "where", "while", "dyn", "abstract", "become",
+                    "box", "do", "final", "macro", "override", "priv", "typeof", "unsized",
+                    "virtual", "yield", "async", "await", "try",
+                ]),
+                secondary_keywords: generate_keywords_len(&[
+                    "bool", "char", "i8", "i16", "i32", "i64", "isize", "u8", "u16", "u32", "u64",
+                    "usize", "f32", "f64",
+                ]),
+            },
+            indentation: IndentationRules {
+                indent_after: vec!['{', '(', '['],
+                dedent_on: vec!['}', ')', ']'],
+            },
+        }
+    }
 }
 
 impl Default for FileType {
@@ -107,6 +176,25 @@ impl Default for FileType {
         Self {
             name: String::from("No filetype"),
             hl_opts: HighlightingOptions::default(),
+            indentation: IndentationRules::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_diff_by_extension_or_name() {
+        assert!(FileType::from("fix.diff").highlighting_options().diff());
+        assert!(FileType::from("FIX.PATCH").highlighting_options().diff());
+        assert!(!FileType::from("main.rs").highlighting_options().diff());
+        assert_eq!(FileType::from("fix.diff").name(), "Diff");
+        assert_eq!(
+            FileType::from_name("patch").map(|file_type| file_type.name()),
+            Some("Diff".to_owned())
+        );
+        assert!(FileType::from_name("unknown").is_none());
+    }
+}
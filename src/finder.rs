@@ -0,0 +1,127 @@
+use crate::fuzzy;
+
+use ignore::WalkBuilder;
+use std::path::Path;
+
+/// Maximum number of files collected from the project
+const MAX_FILES: usize = 100_000;
+
+/// Fuzzy finder of files in project, ignored files (e.g. by `.gitignore`) are skipped
+pub struct Finder {
+    files: Vec<String>,
+    /// Files matching the pattern, the best match first
+    matches: Vec<String>,
+    selected: usize,
+}
+
+impl Finder {
+    pub fn new(root: &Path) -> Self {
+        let mut files: Vec<String> = WalkBuilder::new(root)
+            .require_git(false)
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file())
+            })
+            .take(MAX_FILES)
+            .map(|entry| {
+                let path = entry.path();
+                path.strip_prefix(root)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        files.sort();
+        Self {
+            matches: files.clone(),
+            files,
+            selected: 0,
+        }
+    }
+
+    /// Rank files by how well they match `pattern`, the first match is selected
+    pub fn filter(&mut self, pattern: &str) {
+        let mut scored: Vec<(i64, &String)> = self
+            .files
+            .iter()
+            .filter_map(|file| fuzzy::score(pattern, file).map(|score| (score, file)))
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+        self.matches = scored.into_iter().map(|(_, file)| file.clone()).collect();
+        self.selected = 0;
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected.saturating_add(1) < self.matches.len() {
+            self.selected = self.selected.saturating_add(1);
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.matches.get(self.selected).map(String::as_str)
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn matches(&self) -> &[String] {
+        &self.matches
+    }
+
+    /// Number of all files in project
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    #[test]
+    fn finds_files_not_ignored() {
+        let root = env::temp_dir().join(format!("hecto-finder-{}", process::id()));
+        for directory in ["src", "target"] {
+            fs::create_dir_all(root.join(directory)).unwrap();
+        }
+        for (file, content) in [
+            (".gitignore", "target/\n"),
+            ("README.md", ""),
+            ("src/main.rs", ""),
+            ("src/editor.rs", ""),
+            ("target/main.rs", ""),
+        ] {
+            fs::write(root.join(file), content).unwrap();
+        }
+        let mut finder = Finder::new(&root);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(finder.file_count(), 3);
+        assert_eq!(
+            finder.matches(),
+            ["README.md", "src/editor.rs", "src/main.rs"]
+        );
+
+        finder.filter("main");
+        assert_eq!(finder.matches(), ["src/main.rs"]);
+        finder.filter("sr");
+        assert_eq!(finder.selected(), Some("src/main.rs"));
+        finder.select_next();
+        finder.select_next();
+        assert_eq!(finder.selected_index(), 1);
+        assert_eq!(finder.selected(), Some("src/editor.rs"));
+        finder.select_previous();
+        finder.select_previous();
+        assert_eq!(finder.selected_index(), 0);
+        finder.filter("xyz");
+        assert_eq!(finder.selected(), None);
+    }
+}
@@ -0,0 +1,62 @@
+/// Score how well `pattern` matches `candidate`, `None` if the pattern isn't a subsequence of candidate.
+///
+/// Matching is case-insensitive. Higher score is better, matches which are consecutive,
+/// at the start of candidate or after a separator (e.g. `/`, `_` or `-`) are preferred.
+pub fn score(pattern: &str, candidate: &str) -> Option<i64> {
+    let mut score: i64 = 0;
+    let mut candidate_chars = candidate.chars().enumerate();
+    let mut previous_match: Option<usize> = None;
+    let mut previous_char: Option<char> = None;
+    for pattern_char in pattern.chars() {
+        let pattern_char = pattern_char.to_lowercase().next().unwrap_or(pattern_char);
+        loop {
+            let (index, c) = candidate_chars.next()?;
+            let before = previous_char;
+            previous_char = Some(c);
+            if c.to_lowercase().next() != Some(pattern_char) {
+                continue;
+            }
+            score = score.saturating_add(1);
+            if previous_match.is_some_and(|previous| previous.saturating_add(1) == index) {
+                score = score.saturating_add(5);
+            }
+            match before {
+                None => score = score.saturating_add(8),
+                Some(before) if !before.is_alphanumeric() => score = score.saturating_add(4),
+                _ => (),
+            }
+            previous_match = Some(index);
+            break;
+        }
+    }
+    // Shorter candidates are preferred between otherwise equal matches
+    let length = i64::try_from(candidate.chars().count()).unwrap_or(i64::MAX);
+    Some(score.saturating_mul(64).saturating_sub(length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_subsequences_ignoring_case() {
+        assert!(score("sc", "src/command.rs").is_some());
+        assert!(score("SRC", "src/main.rs").is_some());
+        assert!(score("", "anything").is_some());
+        assert!(score("cs", "sc").is_none());
+        assert!(score("x", "").is_none());
+    }
+
+    #[test]
+    fn prefers_consecutive_and_word_start_matches() {
+        let best = |candidates: &[&'static str]| {
+            candidates
+                .iter()
+                .copied()
+                .max_by_key(|candidate| score("main", candidate))
+        };
+        assert_eq!(best(&["m_a_i_n.rs", "main.rs"]), Some("main.rs"));
+        assert_eq!(best(&["domain.rs", "src/main.rs"]), Some("src/main.rs"));
+        assert_eq!(best(&["src/main.rs", "main.rs"]), Some("main.rs"));
+    }
+}
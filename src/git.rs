@@ -0,0 +1,341 @@
+use crate::diff::{self, Hunk};
+use crate::Row;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Kind of change of a row against the committed version of file
+#[derive(PartialEq, Clone, Copy)]
+pub enum Marker {
+    Added,
+    Modified,
+    /// Rows were removed right above this row, or below the last row
+    Deleted,
+}
+
+/// Lines of `file_name` in the last commit (`HEAD`) of its repository, read by local `git`.
+/// Returns `None` if the file isn't committed or git isn't available.
+pub fn head_lines(file_name: &str) -> Option<Vec<String>> {
+    let name = Path::new(file_name).file_name()?.to_str()?;
+    let text = run(file_name, &["show", &format!("HEAD:./{}", name)], None)?;
+    let mut lines: Vec<String> = text.lines().map(str::to_owned).collect();
+    // Documents have an empty last row when file ends with new line
+    if text.ends_with('\n') {
+        lines.push(String::new());
+    }
+    Some(lines)
+}
+
+/// Commit which last changed a line
+pub struct BlameLine {
+    pub commit: String,
+    pub author: String,
+    pub time: SystemTime,
+}
+
+impl BlameLine {
+    /// Line isn't committed yet, it was changed in the working tree or in the editor
+    pub fn is_uncommitted(&self) -> bool {
+        self.commit.bytes().all(|byte| byte == b'0')
+    }
+}
+
+/// Commits which last changed each line of `contents`, the edited version of `file_name`.
+/// Lines without annotation, e.g. the empty last row, are `None`.
+pub fn blame(file_name: &str, contents: &str) -> Option<Vec<Option<BlameLine>>> {
+    let name = Path::new(file_name).file_name()?.to_str()?;
+    let output = run(
+        file_name,
+        &["blame", "--porcelain", "--contents", "-", "--", name],
+        Some(contents),
+    )?;
+    let mut commits: HashMap<String, (String, u64)> = HashMap::new();
+    let mut lines: Vec<Option<BlameLine>> = Vec::new();
+    let mut commit = String::new();
+    let mut line: usize = 0;
+    for output_line in output.lines() {
+        if output_line.starts_with('\t') {
+            let (author, time) = commits.get(&commit).cloned().unwrap_or_default();
+            let index = line.saturating_sub(1);
+            if lines.len() <= index {
+                lines.resize_with(index.saturating_add(1), || None);
+            }
+            if let Some(blame) = lines.get_mut(index) {
+                *blame = Some(BlameLine {
+                    commit: commit.clone(),
+                    author,
+                    time: UNIX_EPOCH
+                        .checked_add(Duration::from_secs(time))
+                        .unwrap_or(UNIX_EPOCH),
+                });
+            }
+        } else if let Some(author) = output_line.strip_prefix("author ") {
+            author.clone_into(&mut commits.entry(commit.clone()).or_default().0);
+        } else if let Some(time) = output_line.strip_prefix("author-time ") {
+            commits.entry(commit.clone()).or_default().1 = time.parse().unwrap_or(0);
+        } else {
+            // Header of line: `<commit> <original line> <final line> [<number of lines>]`
+            let mut fields = output_line.split(' ');
+            if let (Some(hash), Some(_), Some(final_line)) =
+                (fields.next(), fields.next(), fields.next())
+            {
+                if hash.len() == 40 && hash.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                    hash.clone_into(&mut commit);
+                    line = final_line.parse().unwrap_or(0);
+                }
+            }
+        }
+    }
+    Some(lines)
+}
+
+/// Full message and metadata of `commit` in repository of `file_name`
+pub fn commit_message(file_name: &str, commit: &str) -> Option<Vec<String>> {
+    let text = run(
+        file_name,
+        &["show", "--no-patch", "--format=fuller", commit],
+        None,
+    )?;
+    Some(text.lines().map(str::to_owned).collect())
+}
+
+/// Output of git command run in directory of `file_name`, `input` is written to its standard input
+fn run(file_name: &str, args: &[&str], input: Option<&str>) -> Option<String> {
+    let directory = Path::new(file_name)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    if let Some(input) = input {
+        // Standard input is closed when dropped, so that git stops reading it
+        let written = child
+            .stdin
+            .take()
+            .map(|mut stdin| stdin.write_all(input.as_bytes()));
+        if !matches!(written, Some(Ok(()))) {
+            // Child is waited for so that it doesn't stay as a zombie process
+            child.kill().ok();
+            child.wait().ok();
+            return None;
+        }
+    }
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Changes of document rows against the committed version of its file
+pub struct Changes {
+    base: Vec<String>,
+    hunks: Vec<Hunk>,
+    markers: Vec<Option<Marker>>,
+    /// Revision of document the changes were computed for
+    revision: Option<usize>,
+}
+
+impl Changes {
+    pub fn new(base: Vec<String>) -> Self {
+        Self {
+            base,
+            hunks: Vec::new(),
+            markers: Vec::new(),
+            revision: None,
+        }
+    }
+
+    /// Compare `rows` with the committed version, unless it was done for the same `revision` already
+    pub fn update(&mut self, rows: &[Row], revision: usize) {
+        if self.revision == Some(revision) {
+            return;
+        }
+        self.revision = Some(revision);
+        let lines: Vec<String> = rows.iter().map(Row::get_string).collect();
+        self.hunks = diff::hunks(&diff::diff(&self.base, &lines), 0);
+        self.markers = vec![None; lines.len()];
+        for hunk in &self.hunks {
+            if hunk.new_len == 0 {
+                let y = hunk.new_start.min(lines.len().saturating_sub(1));
+                if let Some(marker) = self.markers.get_mut(y) {
+                    marker.get_or_insert(Marker::Deleted);
+                }
+                continue;
+            }
+            let marker = if hunk.old_len == 0 {
+                Marker::Added
+            } else {
+                Marker::Modified
+            };
+            for y in hunk.new_start..hunk.new_start.saturating_add(hunk.new_len) {
+                if let Some(row_marker) = self.markers.get_mut(y) {
+                    *row_marker = Some(marker);
+                }
+            }
+        }
+    }
+
+    pub fn marker(&self, y: usize) -> Option<Marker> {
+        self.markers.get(y).copied().flatten()
+    }
+
+    /// Row where `hunk` is shown, the row with its marker
+    fn first_row(&self, hunk: &Hunk) -> usize {
+        hunk.new_start.min(self.markers.len().saturating_sub(1))
+    }
+
+    /// Hunk shown at row `y`
+    pub fn hunk_at(&self, y: usize) -> Option<&Hunk> {
+        self.hunks.iter().find(|hunk| {
+            let start = self.first_row(hunk);
+            y >= start && y < start.saturating_add(hunk.new_len.max(1))
+        })
+    }
+
+    /// First row of the nearest hunk below row `y`
+    pub fn next_hunk(&self, y: usize) -> Option<usize> {
+        self.hunks
+            .iter()
+            .map(|hunk| self.first_row(hunk))
+            .find(|&start| start > y)
+    }
+
+    /// First row of the nearest hunk above row `y`
+    pub fn previous_hunk(&self, y: usize) -> Option<usize> {
+        let current = self.hunk_at(y).map(|hunk| self.first_row(hunk));
+        self.hunks
+            .iter()
+            .map(|hunk| self.first_row(hunk))
+            .rfind(|&start| start < current.unwrap_or(y))
+    }
+
+    /// Committed lines which `hunk` replaces
+    pub fn original(&self, hunk: &Hunk) -> &[String] {
+        self.base
+            .get(hunk.old_start..hunk.old_start.saturating_add(hunk.old_len))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::PathBuf, process};
+
+    /// Temporary repository with `file.txt` committed with `contents`
+    fn repository(name: &str, contents: &str) -> (PathBuf, String) {
+        let directory = env::temp_dir().join(format!("hecto-git-{}-{}", name, process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let file = directory.join("file.txt");
+        fs::write(&file, contents).unwrap();
+        let file_name = file.to_string_lossy().into_owned();
+        for args in [
+            &["init", "-q"][..],
+            &["add", "file.txt"],
+            &[
+                "-c",
+                "user.name=Author",
+                "-c",
+                "user.email=author@example.com",
+                "commit",
+                "-q",
+                "-m",
+                "Add file",
+            ],
+        ] {
+            run(&file_name, args, None).unwrap();
+        }
+        (directory, file_name)
+    }
+
+    fn changes(base: &[&str], lines: &[&str]) -> Changes {
+        let mut changes = Changes::new(base.iter().map(|line| (*line).to_owned()).collect());
+        let rows: Vec<Row> = lines.iter().map(|line| Row::from(*line)).collect();
+        changes.update(&rows, 1);
+        changes
+    }
+
+    #[test]
+    fn reads_committed_lines() {
+        let (directory, file_name) = repository("head", "a\nb\n");
+        fs::write(&file_name, "changed").unwrap();
+        let lines = head_lines(&file_name);
+        let untracked = directory.join("untracked.txt");
+        fs::write(&untracked, "").unwrap();
+        let untracked_lines = head_lines(&untracked.to_string_lossy());
+        fs::remove_dir_all(directory).unwrap();
+        assert_eq!(lines.unwrap(), ["a", "b", ""]);
+        assert!(untracked_lines.is_none());
+    }
+
+    #[test]
+    fn blames_edited_lines() {
+        let (directory, file_name) = repository("blame", "a\nb\n");
+        let lines = blame(&file_name, "a\nchanged\n");
+        let message = lines
+            .as_ref()
+            .and_then(|lines| lines.first()?.as_ref())
+            .and_then(|line| commit_message(&file_name, &line.commit));
+        fs::remove_dir_all(directory).unwrap();
+        let lines = lines.unwrap();
+        assert_eq!(lines.len(), 2);
+        let committed = lines[0].as_ref().unwrap();
+        assert_eq!(committed.author, "Author");
+        assert!(!committed.is_uncommitted());
+        assert!(committed.time > UNIX_EPOCH);
+        assert!(lines[1].as_ref().unwrap().is_uncommitted());
+        assert!(message
+            .unwrap()
+            .iter()
+            .any(|line| line.trim() == "Add file"));
+    }
+
+    #[test]
+    fn marks_changed_rows() {
+        let changes = changes(&["a", "b", "c", "d", ""], &["a", "B", "c", "new", "d"]);
+        assert!(changes.marker(0).is_none());
+        assert!(changes.marker(1) == Some(Marker::Modified));
+        assert!(changes.marker(2).is_none());
+        assert!(changes.marker(3) == Some(Marker::Added));
+        // Removed empty last row is shown on the last row
+        assert!(changes.marker(4) == Some(Marker::Deleted));
+    }
+
+    #[test]
+    fn keeps_changes_of_same_revision() {
+        let mut changes = changes(&["a"], &["b"]);
+        changes.update(&[Row::from("a")], 1);
+        assert!(changes.marker(0) == Some(Marker::Modified));
+        changes.update(&[Row::from("a")], 2);
+        assert!(changes.marker(0).is_none());
+    }
+
+    #[test]
+    fn moves_between_hunks() {
+        let changes = changes(&["a", "b", "c", "d", "e"], &["x", "b", "c", "e"]);
+        assert!(changes.marker(3) == Some(Marker::Deleted));
+        assert_eq!(changes.next_hunk(0), Some(3));
+        assert_eq!(changes.next_hunk(3), None);
+        assert_eq!(changes.previous_hunk(3), Some(0));
+        assert_eq!(changes.previous_hunk(0), None);
+        let hunk = changes.hunk_at(3).unwrap();
+        assert_eq!(changes.original(hunk), ["d"]);
+        assert_eq!(changes.original(changes.hunk_at(0).unwrap()), ["a"]);
+        assert!(changes.hunk_at(1).is_none());
+    }
+}
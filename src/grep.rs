@@ -0,0 +1,159 @@
+use crate::{Position, Row, SearchDirection, SearchOptions};
+
+use ignore::{Walk, WalkBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Line of a file matching the searched text
+pub struct Match {
+    pub file_name: String,
+    pub position: Position,
+    pub line: String,
+}
+
+/// Search of text in all files under a directory, done in slices so that the editor stays responsive.
+/// Ignored (e.g. by `.gitignore`) and binary files are skipped.
+pub struct Grep {
+    query: String,
+    options: SearchOptions,
+    root: PathBuf,
+    walk: Walk,
+    files: usize,
+    matches: usize,
+    done: bool,
+}
+
+impl Grep {
+    pub fn new(root: &Path, query: &str, options: SearchOptions) -> Self {
+        Self {
+            query: query.to_owned(),
+            options,
+            root: root.to_path_buf(),
+            walk: WalkBuilder::new(root).require_git(false).build(),
+            files: 0,
+            matches: 0,
+            done: false,
+        }
+    }
+
+    /// Search next files until `duration` elapses or all files are searched
+    pub fn search(&mut self, duration: Duration) -> Vec<Match> {
+        let start = Instant::now();
+        let mut found = Vec::new();
+        while !self.done && start.elapsed() < duration {
+            match self.walk.next() {
+                Some(Ok(entry)) => {
+                    if entry
+                        .file_type()
+                        .is_some_and(|file_type| file_type.is_file())
+                    {
+                        found.extend(self.search_file(entry.path()));
+                    }
+                }
+                Some(Err(_)) => (),
+                None => self.done = true,
+            }
+        }
+        self.matches = self.matches.saturating_add(found.len());
+        found
+    }
+
+    fn search_file(&mut self, path: &Path) -> Vec<Match> {
+        let mut found = Vec::new();
+        let text = match fs::read(path).map(String::from_utf8) {
+            Ok(Ok(text)) if !text.contains('\0') => text,
+            _ => return found,
+        };
+        self.files = self.files.saturating_add(1);
+        let file_name = path
+            .strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned();
+        let ignore_case = self.options.ignores_case(&self.query);
+        let lowercase_query = self.query.to_lowercase();
+        let query_len = self.query.graphemes(true).count();
+        for (y, line) in text.lines().enumerate() {
+            // Cheap check first, most lines don't match
+            let candidate = if ignore_case {
+                line.to_lowercase().contains(&lowercase_query)
+            } else {
+                line.contains(&self.query)
+            };
+            if !candidate {
+                continue;
+            }
+            let row = Row::from(line);
+            let mut x = 0;
+            while let Some(found_x) =
+                row.find(&self.query, x, SearchDirection::Forward, self.options)
+            {
+                found.push(Match {
+                    file_name: file_name.clone(),
+                    position: Position { x: found_x, y },
+                    line: line.to_owned(),
+                });
+                x = found_x.saturating_add(query_len.max(1));
+            }
+        }
+        found
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Number of text files searched so far
+    pub fn file_count(&self) -> usize {
+        self.files
+    }
+
+    /// Number of matches found so far
+    pub fn match_count(&self) -> usize {
+        self.matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn finds_matches_in_text_files() {
+        let root = env::temp_dir().join(format!("hecto-grep-{}", process::id()));
+        fs::create_dir_all(root.join("target")).unwrap();
+        for (file, content) in [
+            (".gitignore", "target/\n"),
+            ("a.txt", "Needle and needle\nhay\nneedles"),
+            ("binary.dat", "needle\0"),
+            ("target/b.txt", "needle"),
+        ] {
+            fs::write(root.join(file), content).unwrap();
+        }
+        let options = SearchOptions {
+            ignore_case: true,
+            ..SearchOptions::default()
+        };
+        let mut grep = Grep::new(&root, "needle", options);
+        let mut found = Vec::new();
+        while !grep.is_done() {
+            found.extend(grep.search(Duration::from_secs(1)));
+        }
+        fs::remove_dir_all(&root).unwrap();
+        let locations: Vec<(&str, usize, usize)> = found
+            .iter()
+            .map(|found| (found.file_name.as_str(), found.position.y, found.position.x))
+            .collect();
+        assert_eq!(
+            locations,
+            [("a.txt", 0, 0), ("a.txt", 0, 11), ("a.txt", 2, 0)]
+        );
+        assert_eq!(found[2].line, "needles");
+        assert_eq!(grep.match_count(), 3);
+        // Hidden, binary and ignored files aren't searched
+        assert_eq!(grep.file_count(), 1);
+    }
+}
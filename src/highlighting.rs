@@ -9,11 +9,21 @@ pub enum Type {
     MultilineComment,
     PrimaryKeywords,
     SecondaryKeywords,
+    DiffHeader,
+    DiffHunk,
+    DiffAdded,
+    DiffDeleted,
+    ConflictMarker,
+    ConflictOurs,
+    ConflictBase,
+    ConflictTheirs,
     Match, // Highlight match in search
     None,  // No highlighting
 }
 
 impl Type {
+    // Unrelated types may share a color of the palette
+    #[allow(clippy::match_same_arms)]
     pub fn to_color(self) -> color::Rgb {
         match self {
             Type::Number => color::Rgb(220, 163, 163),
@@ -22,6 +32,14 @@ impl Type {
             Type::Comment | Type::MultilineComment => color::Rgb(0x67, 0x95, 0x4f),
             Type::PrimaryKeywords => color::Rgb(181, 137, 0),
             Type::SecondaryKeywords => color::Rgb(42, 161, 152),
+            Type::DiffHeader => color::Rgb(181, 137, 0),
+            Type::DiffHunk => color::Rgb(108, 113, 196),
+            Type::DiffAdded => color::Rgb(133, 153, 0),
+            Type::DiffDeleted => color::Rgb(220, 50, 47),
+            Type::ConflictMarker => color::Rgb(203, 75, 22),
+            Type::ConflictOurs => color::Rgb(133, 153, 0),
+            Type::ConflictBase => color::Rgb(147, 161, 161),
+            Type::ConflictTheirs => color::Rgb(38, 139, 210),
             Type::Match => color::Rgb(38, 139, 210),
             Type::None => color::Rgb(255, 255, 255),
         }
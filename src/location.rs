@@ -0,0 +1,143 @@
+use std::path::Path;
+
+/// Line to go to
+#[derive(PartialEq, Clone, Copy)]
+pub enum Line {
+    /// One-based line number
+    Absolute(usize),
+    /// Number of lines down (positive) or up (negative) from the cursor
+    Relative(isize),
+    /// Percentage of document length
+    Percent(usize),
+}
+
+/// Target of go to, e.g. `42`, `42:7`, `+3`, `-10` or `50%`
+#[derive(PartialEq, Clone, Copy)]
+pub struct Location {
+    pub line: Line,
+    /// One-based column
+    pub column: Option<usize>,
+}
+
+impl Location {
+    /// Parse `line[:column]` where line is a number, `+N`, `-N` or `N%`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.trim().splitn(2, ':');
+        let line = parts.next()?.trim();
+        let column = match parts.next() {
+            Some(column) => Some(column.trim().parse::<usize>().ok()?),
+            None => None,
+        };
+        let line = if let Some(percent) = line.strip_suffix('%') {
+            Line::Percent(percent.trim().parse().ok()?)
+        } else if let Some(lines) = line.strip_prefix('+') {
+            Line::Relative(lines.parse().ok()?)
+        } else if line.starts_with('-') {
+            Line::Relative(line.parse().ok()?)
+        } else {
+            Line::Absolute(line.parse().ok()?)
+        };
+        Some(Self { line, column })
+    }
+
+    /// Zero-based line index in document of `len` lines, with the cursor at line `current`
+    pub fn line_index(&self, current: usize, len: usize) -> usize {
+        let last = len.saturating_sub(1);
+        let index = match self.line {
+            Line::Absolute(line) => line.saturating_sub(1),
+            Line::Relative(lines) => {
+                if lines < 0 {
+                    current.saturating_sub(lines.unsigned_abs())
+                } else {
+                    current.saturating_add(lines.unsigned_abs())
+                }
+            }
+            Line::Percent(percent) => len
+                .saturating_mul(percent.min(100))
+                .saturating_add(50)
+                .checked_div(100)
+                .unwrap_or(0)
+                .saturating_sub(1),
+        };
+        index.min(last)
+    }
+}
+
+/// Split command line argument into file name and location, e.g. `src/main.rs:42:7`.
+/// Name of an existing file is never split, so files with `:` in name can still be opened.
+pub fn split_file_location(argument: &str) -> (&str, Option<Location>) {
+    if Path::new(argument).exists() {
+        return (argument, None);
+    }
+    // Try `file:line:column` first, then `file:line`
+    for separators in [2_usize, 1] {
+        let mut parts = argument.rsplitn(separators.saturating_add(1), ':');
+        let numbers: Vec<&str> = parts.by_ref().take(separators).collect();
+        if let Some(file_name) = parts.next() {
+            if file_name.is_empty()
+                || !numbers
+                    .iter()
+                    .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+            {
+                continue;
+            }
+            let location: Vec<&str> = numbers.into_iter().rev().collect();
+            if let Some(location) = Location::parse(&location.join(":")) {
+                return (file_name, Some(location));
+            }
+        }
+    }
+    (argument, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(text: &str) -> Option<(Line, Option<usize>)> {
+        Location::parse(text).map(|location| (location.line, location.column))
+    }
+
+    #[test]
+    fn parses_locations() {
+        assert!(parsed("42") == Some((Line::Absolute(42), None)));
+        assert!(parsed(" 42:7 ") == Some((Line::Absolute(42), Some(7))));
+        assert!(parsed("+3") == Some((Line::Relative(3), None)));
+        assert!(parsed("-10") == Some((Line::Relative(-10), None)));
+        assert!(parsed("50%") == Some((Line::Percent(50), None)));
+        assert!(parsed("").is_none());
+        assert!(parsed("x").is_none());
+        assert!(parsed("4:y").is_none());
+    }
+
+    #[test]
+    fn finds_line_index() {
+        let index = |text: &str| Location::parse(text).unwrap().line_index(10, 100);
+        assert_eq!(index("1"), 0);
+        assert_eq!(index("0"), 0);
+        assert_eq!(index("500"), 99);
+        assert_eq!(index("+5"), 15);
+        assert_eq!(index("-20"), 0);
+        assert_eq!(index("50%"), 49);
+        assert_eq!(index("100%"), 99);
+        assert_eq!(index("0%"), 0);
+    }
+
+    #[test]
+    fn splits_file_location() {
+        let split = |argument| {
+            let (file_name, location) = split_file_location(argument);
+            (
+                file_name,
+                location.map(|location| (location.line, location.column)),
+            )
+        };
+        assert!(
+            split("src/missing.rs:42:7") == ("src/missing.rs", Some((Line::Absolute(42), Some(7))))
+        );
+        assert!(split("src/missing.rs:42") == ("src/missing.rs", Some((Line::Absolute(42), None))));
+        assert!(split("src/missing.rs") == ("src/missing.rs", None));
+        assert!(split("a:b") == ("a:b", None));
+        assert!(split(":42") == (":42", None));
+    }
+}
@@ -0,0 +1,455 @@
+use crate::terminal::{CTRL_BACKSPACE, CTRL_DELETE};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::PathBuf;
+use termion::event::Key;
+
+/// Maximum number of runs of a macro replayed until it fails
+pub const MAX_MACRO_RUNS: usize = 10_000;
+/// Maximum depth of macros replaying other macros
+const MAX_MACRO_DEPTH: usize = 16;
+
+/// Keys with names used in the macro file, other keys are written as characters with modifiers, e.g. `C-x`
+const KEY_NAMES: [(Key, &str); 35] = [
+    (Key::Char('\n'), "Enter"),
+    (Key::Char('\t'), "Tab"),
+    (Key::Char(' '), "Space"),
+    (Key::Backspace, "Backspace"),
+    (Key::Delete, "Delete"),
+    (Key::Insert, "Insert"),
+    (Key::Esc, "Esc"),
+    (Key::BackTab, "BackTab"),
+    (Key::Up, "Up"),
+    (Key::Down, "Down"),
+    (Key::Left, "Left"),
+    (Key::Right, "Right"),
+    (Key::Home, "Home"),
+    (Key::End, "End"),
+    (Key::PageUp, "PageUp"),
+    (Key::PageDown, "PageDown"),
+    (Key::CtrlUp, "C-Up"),
+    (Key::CtrlDown, "C-Down"),
+    (Key::CtrlLeft, "C-Left"),
+    (Key::CtrlRight, "C-Right"),
+    (Key::CtrlHome, "C-Home"),
+    (Key::CtrlEnd, "C-End"),
+    (Key::ShiftUp, "S-Up"),
+    (Key::ShiftDown, "S-Down"),
+    (Key::ShiftLeft, "S-Left"),
+    (Key::ShiftRight, "S-Right"),
+    (Key::AltUp, "M-Up"),
+    (Key::AltDown, "M-Down"),
+    (Key::AltLeft, "M-Left"),
+    (Key::AltRight, "M-Right"),
+    (Key::Alt(' '), "M-Space"),
+    (Key::Ctrl(' '), "C-Space"),
+    (CTRL_BACKSPACE, "C-Backspace"),
+    (CTRL_DELETE, "C-Delete"),
+    (Key::Null, "Null"),
+];
+
+/// Name of `key` in the macro file, e.g. `a`, `C-s`, `M-Left` or `Enter`
+pub fn key_name(key: Key) -> Option<String> {
+    if let Some((_, name)) = KEY_NAMES.iter().find(|(named, _)| *named == key) {
+        return Some((*name).to_owned());
+    }
+    match key {
+        Key::Char(c) if !c.is_whitespace() && !c.is_control() => Some(c.to_string()),
+        Key::Ctrl(c) if !c.is_whitespace() && !c.is_control() => Some(format!("C-{}", c)),
+        Key::Alt(c) if !c.is_whitespace() && !c.is_control() => Some(format!("M-{}", c)),
+        Key::Alt('\x7f') => Some("M-Backspace".to_owned()),
+        Key::F(number) => Some(format!("F{}", number)),
+        _ => None,
+    }
+}
+
+/// Key written as `name` in the macro file
+pub fn parse_key(name: &str) -> Option<Key> {
+    if let Some((key, _)) = KEY_NAMES.iter().find(|(_, named)| *named == name) {
+        return Some(*key);
+    }
+    if name == "M-Backspace" {
+        return Some(Key::Alt('\x7f'));
+    }
+    let single = |text: &str| {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    if let Some(c) = single(name) {
+        return Some(Key::Char(c));
+    }
+    if let Some(c) = name.strip_prefix("C-").and_then(single) {
+        return Some(Key::Ctrl(c));
+    }
+    if let Some(c) = name.strip_prefix("M-").and_then(single) {
+        return Some(Key::Alt(c));
+    }
+    name.strip_prefix('F')
+        .and_then(|number| number.parse().ok())
+        .map(Key::F)
+}
+
+/// Whether macro can be bound to `key`. Keys typing or deleting text, including Enter and Tab, are
+/// needed for editing.
+pub fn is_bindable(key: Key) -> bool {
+    !matches!(key, Key::Char(_) | Key::Backspace)
+}
+
+/// Whether `key` moves the cursor. Replaying of macro fails when the cursor doesn't move, e.g. at the
+/// end of document.
+pub fn is_motion(key: Key) -> bool {
+    matches!(
+        key,
+        Key::Up
+            | Key::Down
+            | Key::Left
+            | Key::Right
+            | Key::PageUp
+            | Key::PageDown
+            | Key::CtrlLeft
+            | Key::CtrlRight
+            | Key::CtrlUp
+            | Key::CtrlDown
+            | Key::CtrlHome
+            | Key::CtrlEnd
+    )
+}
+
+/// Name of macro and number of its replays from `arguments` of play command, `<name> [<count> | all]`.
+/// Count is `None` for `all`, the macro is replayed until it fails. Invalid count is the error.
+pub fn parse_play_arguments(arguments: &str) -> Result<(&str, Option<usize>), &str> {
+    let mut words = arguments.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    match words.next() {
+        None => Ok((name, Some(1))),
+        Some("all") => Ok((name, None)),
+        Some(count) => count
+            .parse()
+            .map(|count| (name, Some(count)))
+            .map_err(|_| count),
+    }
+}
+
+/// Macro being recorded
+pub struct Recording {
+    name: String,
+    keys: Vec<Key>,
+    /// Index of the first key of the command being processed, where recording ends if the command stops it
+    command_start: usize,
+}
+
+impl Recording {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            keys: Vec::new(),
+            command_start: 0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Record key read from terminal
+    pub fn push(&mut self, key: Key) {
+        self.keys.push(key);
+    }
+
+    /// The last recorded key starts a new command
+    pub fn start_command(&mut self) {
+        self.command_start = self.keys.len().saturating_sub(1);
+    }
+
+    /// Name and keys of the recorded macro. Keys of the command which stopped recording aren't part of it.
+    pub fn finish(mut self) -> (String, Vec<Key>) {
+        self.keys.truncate(self.command_start);
+        (self.name, self.keys)
+    }
+}
+
+/// Keys of macros being replayed, macros can replay other macros
+#[derive(Default)]
+pub struct Replay {
+    /// Keys left in the innermost macro, they are read before keys from terminal
+    keys: VecDeque<Key>,
+    /// Keys left in macros which replay the innermost one
+    outer: Vec<VecDeque<Key>>,
+    /// Replayed macro needed more keys than it has, e.g. it ended in a prompt
+    aborted: bool,
+}
+
+impl Replay {
+    /// Start replaying a macro inside the current one. Returns `false` if macros replay each other too deeply.
+    pub fn enter(&mut self) -> bool {
+        if self.outer.len() >= MAX_MACRO_DEPTH {
+            return false;
+        }
+        if self.outer.is_empty() {
+            self.aborted = false;
+        }
+        self.outer.push(mem::take(&mut self.keys));
+        true
+    }
+
+    /// Replay `keys` of the innermost macro once more
+    pub fn start_run(&mut self, keys: &[Key]) {
+        self.keys = keys.iter().copied().collect();
+    }
+
+    /// Next key of the current run of the innermost macro
+    pub fn next_key(&mut self) -> Option<Key> {
+        self.keys.pop_front()
+    }
+
+    /// Key read by a command while macros are replayed, `None` when they aren't. Prompt left open by
+    /// macro is cancelled by ESC, keys typed meanwhile aren't part of the replay.
+    pub fn read_key(&mut self) -> Option<Key> {
+        if let Some(key) = self.keys.pop_front() {
+            return Some(key);
+        }
+        if self.outer.is_empty() {
+            return None;
+        }
+        self.aborted = true;
+        Some(Key::Esc)
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Stop replaying the innermost macro. Returns `true` if it was the outermost one.
+    pub fn leave(&mut self) -> bool {
+        self.keys = self.outer.pop().unwrap_or_default();
+        self.outer.is_empty()
+    }
+}
+
+/// Recorded keyboard macros by register name, and keys bound to them
+#[derive(Default)]
+pub struct Macros {
+    pub registers: HashMap<String, Vec<Key>>,
+    pub bindings: HashMap<Key, String>,
+}
+
+impl Macros {
+    /// Bind `key` to macro `name`, or unbind it without name. Returns `false` if there is no such macro.
+    pub fn bind(&mut self, key: Key, name: Option<&str>) -> bool {
+        match name {
+            Some(name) if self.registers.contains_key(name) => {
+                self.bindings.insert(key, name.to_owned());
+                true
+            }
+            Some(_) => false,
+            None => {
+                self.bindings.remove(&key);
+                true
+            }
+        }
+    }
+
+    /// File with saved macros, `$XDG_CONFIG_HOME/hecto/macros` or `~/.config/hecto/macros`
+    fn path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|directory| !directory.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|directory| directory.join("hecto").join("macros"))
+    }
+
+    /// Macros saved by `save`, no macros if the file doesn't exist or can't be read.
+    /// Lines are `macro <name> <key>...` and `bind <key> <name>`.
+    pub fn load() -> Self {
+        let mut macros = Self::default();
+        let text = match Self::path().map(fs::read_to_string) {
+            Some(Ok(text)) => text,
+            _ => return macros,
+        };
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("macro"), Some(name)) => {
+                    let keys: Option<Vec<Key>> = words.map(parse_key).collect();
+                    if let Some(keys) = keys {
+                        macros.registers.insert(name.to_owned(), keys);
+                    }
+                }
+                (Some("bind"), Some(key)) => {
+                    if let (Some(key), Some(name)) = (parse_key(key), words.next()) {
+                        if is_bindable(key) {
+                            macros.bindings.insert(key, name.to_owned());
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        macros
+    }
+
+    /// Write all macros and bindings to the macro file.
+    /// Returns the number of skipped macros with keys which cannot be written.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file or its directory cannot be written
+    pub fn save(&self) -> io::Result<usize> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut text = String::from("# hecto keyboard macros\n");
+        let mut skipped: usize = 0;
+        let mut names: Vec<&String> = self.registers.keys().collect();
+        names.sort();
+        for name in names {
+            let keys: Option<Vec<String>> = self
+                .registers
+                .get(name)
+                .map_or_else(Vec::new, Clone::clone)
+                .into_iter()
+                .map(key_name)
+                .collect();
+            if let Some(keys) = keys {
+                text.push_str(&format!("macro {} {}\n", name, keys.join(" ")));
+            } else {
+                skipped = skipped.saturating_add(1);
+            }
+        }
+        let mut bindings: Vec<(String, &String)> = self
+            .bindings
+            .iter()
+            .filter_map(|(key, name)| key_name(*key).map(|key| (key, name)))
+            .collect();
+        bindings.sort();
+        for (key, name) in bindings {
+            text.push_str(&format!("bind {} {}\n", key, name));
+        }
+        fs::write(path, text)?;
+        Ok(skipped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_keys() {
+        assert_eq!(key_name(Key::Char('a')).as_deref(), Some("a"));
+        assert_eq!(key_name(Key::Char('\n')).as_deref(), Some("Enter"));
+        assert_eq!(key_name(Key::Ctrl('s')).as_deref(), Some("C-s"));
+        assert_eq!(key_name(Key::Alt('x')).as_deref(), Some("M-x"));
+        assert_eq!(key_name(Key::AltLeft).as_deref(), Some("M-Left"));
+        assert_eq!(key_name(Key::Alt('\x7f')).as_deref(), Some("M-Backspace"));
+        assert_eq!(key_name(Key::F(5)).as_deref(), Some("F5"));
+        assert_eq!(key_name(Key::Char('\r')), None);
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(parse_key("a"), Some(Key::Char('a')));
+        assert_eq!(parse_key("Space"), Some(Key::Char(' ')));
+        assert_eq!(parse_key("C-s"), Some(Key::Ctrl('s')));
+        assert_eq!(parse_key("M-x"), Some(Key::Alt('x')));
+        assert_eq!(parse_key("C-Home"), Some(Key::CtrlHome));
+        assert_eq!(parse_key("M-Backspace"), Some(Key::Alt('\x7f')));
+        assert_eq!(parse_key("F12"), Some(Key::F(12)));
+        assert_eq!(parse_key("C-ab"), None);
+        assert_eq!(parse_key("Fx"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn named_keys_round_trip() {
+        for (key, name) in KEY_NAMES {
+            assert_eq!(key_name(key).as_deref(), Some(name));
+            assert_eq!(parse_key(name), Some(key));
+        }
+    }
+
+    #[test]
+    fn parses_play_arguments() {
+        assert_eq!(parse_play_arguments("a"), Ok(("a", Some(1))));
+        assert_eq!(parse_play_arguments("a 3"), Ok(("a", Some(3))));
+        assert_eq!(parse_play_arguments("a all"), Ok(("a", None)));
+        assert_eq!(parse_play_arguments("a x"), Err("x"));
+    }
+
+    #[test]
+    fn records_keys_before_stopping_command() {
+        let mut recording = Recording::new("a");
+        for key in [Key::Char('x'), Key::Down, Key::Ctrl('e')] {
+            recording.push(key);
+            recording.start_command();
+        }
+        // Command line typed to stop recording
+        for c in "record".chars() {
+            recording.push(Key::Char(c));
+        }
+        assert_eq!(recording.name(), "a");
+        assert_eq!(
+            recording.finish(),
+            ("a".to_owned(), vec![Key::Char('x'), Key::Down])
+        );
+    }
+
+    #[test]
+    fn replays_nested_macros() {
+        let mut replay = Replay::default();
+        assert_eq!(replay.read_key(), None);
+        assert!(replay.enter());
+        replay.start_run(&[Key::Char('a'), Key::Char('b')]);
+        assert_eq!(replay.next_key(), Some(Key::Char('a')));
+        assert!(replay.enter());
+        replay.start_run(&[Key::Char('c')]);
+        assert_eq!(replay.read_key(), Some(Key::Char('c')));
+        // Prompt of the inner macro got no more keys
+        assert_eq!(replay.read_key(), Some(Key::Esc));
+        assert!(replay.is_aborted());
+        assert!(!replay.leave());
+        assert_eq!(replay.next_key(), Some(Key::Char('b')));
+        assert!(replay.leave());
+        assert_eq!(replay.read_key(), None);
+    }
+
+    #[test]
+    fn limits_depth_of_replay() {
+        let mut replay = Replay::default();
+        for _ in 0..MAX_MACRO_DEPTH {
+            assert!(replay.enter());
+        }
+        assert!(!replay.enter());
+    }
+
+    #[test]
+    fn binds_existing_macros() {
+        let mut macros = Macros::default();
+        macros.registers.insert("a".to_owned(), vec![Key::Down]);
+        assert!(macros.bind(Key::F(2), Some("a")));
+        assert!(!macros.bind(Key::F(3), Some("b")));
+        assert_eq!(
+            macros.bindings.get(&Key::F(2)).map(String::as_str),
+            Some("a")
+        );
+        assert!(macros.bind(Key::F(2), None));
+        assert!(macros.bindings.is_empty());
+    }
+
+    #[test]
+    fn binds_only_keys_not_editing_text() {
+        assert!(is_bindable(Key::Ctrl('x')));
+        assert!(is_bindable(Key::F(2)));
+        assert!(!is_bindable(Key::Char('a')));
+        assert!(!is_bindable(Key::Char('\n')));
+        assert!(!is_bindable(Key::Backspace));
+    }
+}
@@ -10,17 +10,32 @@
     clippy::else_if_without_else,
     clippy::as_conversions
 )]
+mod block;
+mod browser;
+mod command;
+mod compare;
+mod cursors;
+mod diff;
 mod document;
 mod editor;
 mod filetype;
+mod finder;
+mod fuzzy;
+mod git;
+mod grep;
 mod highlighting;
+mod location;
+mod macros;
+mod prompt;
 mod row;
 mod terminal;
+mod timer;
 
 pub use document::Document;
 use editor::Editor;
 pub use editor::Position;
 pub use editor::SearchDirection;
+pub use editor::SearchOptions;
 pub use filetype::FileType;
 pub use filetype::HighlightingOptions;
 pub use row::Row;
@@ -0,0 +1,314 @@
+use crate::terminal::{CTRL_BACKSPACE, CTRL_DELETE};
+use std::fs;
+use std::path::MAIN_SEPARATOR;
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Text typed into a prompt with cursor, edited with readline-like keys
+#[derive(Default)]
+pub struct Line {
+    text: String,
+    /// Grapheme index of cursor
+    cursor: usize,
+    /// Text removed by the last kill command, inserted back by Ctrl-Y
+    pub killed: String,
+}
+
+impl Line {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replace text and move cursor to its end
+    pub fn set_text(&mut self, text: String) {
+        self.text = text;
+        self.cursor = self.len();
+    }
+
+    fn len(&self) -> usize {
+        self.text.graphemes(true).count()
+    }
+
+    /// Byte index of grapheme at index `at`
+    fn byte_index(&self, at: usize) -> usize {
+        self.text
+            .grapheme_indices(true)
+            .nth(at)
+            .map_or(self.text.len(), |(index, _)| index)
+    }
+
+    /// Start of the word before cursor
+    fn previous_word_start(&self) -> usize {
+        let mut start: usize = 0;
+        let mut previous = 0;
+        for word in self.text.split_word_bounds() {
+            if start >= self.cursor {
+                break;
+            }
+            if word.chars().any(char::is_alphanumeric) {
+                previous = start;
+            }
+            start = start.saturating_add(word.graphemes(true).count());
+        }
+        previous
+    }
+
+    /// End of the word after cursor
+    fn next_word_end(&self) -> usize {
+        let mut end: usize = 0;
+        for word in self.text.split_word_bounds() {
+            end = end.saturating_add(word.graphemes(true).count());
+            if end > self.cursor && word.chars().any(char::is_alphanumeric) {
+                return end;
+            }
+        }
+        self.len()
+    }
+
+    fn insert(&mut self, text: &str) {
+        let index = self.byte_index(self.cursor);
+        self.text.insert_str(index, text);
+        self.cursor = self.cursor.saturating_add(text.graphemes(true).count());
+    }
+
+    /// Remove text between grapheme indices `start` and `end` and move cursor to `start`
+    fn remove(&mut self, start: usize, end: usize) -> String {
+        let range = self.byte_index(start)..self.byte_index(end);
+        self.cursor = start;
+        self.text.drain(range).collect()
+    }
+
+    fn kill(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.killed = self.remove(start, end);
+        }
+    }
+
+    /// Edit line according to `key`. Returns `false` for keys which aren't used for editing.
+    pub fn process_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(c) if !c.is_control() => self.insert(&c.to_string()),
+            Key::Backspace if self.cursor > 0 => {
+                self.remove(self.cursor.saturating_sub(1), self.cursor);
+            }
+            Key::Delete if self.cursor < self.len() => {
+                self.remove(self.cursor, self.cursor.saturating_add(1));
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = self.cursor.saturating_add(1).min(self.len()),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.len(),
+            Key::CtrlLeft | Key::Alt('b') => self.cursor = self.previous_word_start(),
+            Key::CtrlRight | Key::Alt('f') => self.cursor = self.next_word_end(),
+            Key::Ctrl('w') | Key::Alt('\x7f') | CTRL_BACKSPACE => {
+                self.kill(self.previous_word_start(), self.cursor);
+            }
+            Key::Alt('d') | CTRL_DELETE => self.kill(self.cursor, self.next_word_end()),
+            Key::Ctrl('k') => self.kill(self.cursor, self.len()),
+            Key::Ctrl('u') => self.kill(0, self.cursor),
+            Key::Ctrl('y') => self.insert(&self.killed.clone()),
+            Key::Backspace | Key::Delete => (),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Part of text starting at grapheme `scroll` which fits into `width` columns,
+    /// `scroll` is adjusted first so that the cursor is visible
+    pub fn visible(&self, scroll: &mut usize, width: usize) -> String {
+        let widths: Vec<usize> = self
+            .text
+            .graphemes(true)
+            .map(|grapheme| grapheme.width().max(1))
+            .collect();
+        let width = width.max(1);
+        if self.cursor < *scroll {
+            *scroll = self.cursor;
+        }
+        // Keep one column free for the cursor after the last grapheme
+        while widths
+            .iter()
+            .take(self.cursor)
+            .skip(*scroll)
+            .sum::<usize>()
+            .saturating_add(1)
+            > width
+        {
+            *scroll = scroll.saturating_add(1);
+        }
+        let mut used: usize = 0;
+        self.text
+            .graphemes(true)
+            .zip(widths)
+            .skip(*scroll)
+            .take_while(|(_, grapheme_width)| {
+                used = used.saturating_add(*grapheme_width);
+                used <= width
+            })
+            .map(|(grapheme, _)| grapheme)
+            .collect()
+    }
+
+    /// Screen width of text between grapheme `scroll` and cursor
+    pub fn cursor_column(&self, scroll: usize) -> usize {
+        self.text
+            .graphemes(true)
+            .take(self.cursor)
+            .skip(scroll)
+            .map(|grapheme| grapheme.width().max(1))
+            .sum()
+    }
+}
+
+/// Complete file system path `partial` as far as it's unambiguous.
+/// Returns completed path and names of all matching entries.
+pub fn complete_path(partial: &str) -> (String, Vec<String>) {
+    let (directory, prefix) = match partial.rfind(MAIN_SEPARATOR) {
+        Some(index) => partial.split_at(index.saturating_add(1)),
+        None => ("", partial),
+    };
+    let entries = match fs::read_dir(if directory.is_empty() { "." } else { directory }) {
+        Ok(entries) => entries,
+        Err(_) => return (partial.to_owned(), Vec::new()),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let mut name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (prefix.is_empty() && name.starts_with('.')) {
+                return None;
+            }
+            if entry.path().is_dir() {
+                name.push(MAIN_SEPARATOR);
+            }
+            Some(name)
+        })
+        .collect();
+    names.sort();
+    let common = names.first().map_or_else(String::new, |first| {
+        let mut common = first.clone();
+        for name in &names {
+            while !name.starts_with(common.as_str()) {
+                common.pop();
+            }
+        }
+        common
+    });
+    if common.len() > prefix.len() {
+        (format!("{}{}", directory, common), names)
+    } else {
+        (partial.to_owned(), names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn line(text: &str) -> Line {
+        let mut line = Line::default();
+        line.set_text(text.to_owned());
+        line
+    }
+
+    #[test]
+    fn kills_previous_word() {
+        for key in [Key::Ctrl('w'), Key::Alt('\x7f'), CTRL_BACKSPACE] {
+            let mut line = line("open some words");
+            assert!(line.process_key(key));
+            assert_eq!(line.text(), "open some ");
+            assert_eq!(line.killed, "words");
+        }
+    }
+
+    #[test]
+    fn kills_next_word() {
+        for key in [Key::Alt('d'), CTRL_DELETE] {
+            let mut line = line("open some words");
+            line.process_key(Key::Home);
+            assert!(line.process_key(key));
+            assert_eq!(line.text(), " some words");
+            assert_eq!(line.killed, "open");
+        }
+    }
+
+    #[test]
+    fn leaves_ctrl_h_to_backspace() {
+        let mut line = line("open file");
+        assert!(!line.process_key(Key::Ctrl('h')));
+        assert_eq!(line.text(), "open file");
+    }
+
+    #[test]
+    fn edits_at_cursor() {
+        let mut line = line("held");
+        for key in [
+            Key::Left,
+            Key::Left,
+            Key::Char('l'),
+            Key::Delete,
+            Key::Home,
+            Key::Char('w'),
+        ] {
+            assert!(line.process_key(key));
+        }
+        assert_eq!(line.text(), "wheld");
+        for key in [Key::Backspace, Key::Backspace, Key::End, Key::Backspace] {
+            assert!(line.process_key(key));
+        }
+        assert_eq!(line.text(), "hel");
+        assert!(!line.process_key(Key::Ctrl('q')));
+    }
+
+    #[test]
+    fn kills_and_yanks() {
+        let mut line = line("one two");
+        line.process_key(Key::Ctrl('a'));
+        line.process_key(Key::Ctrl('k'));
+        assert_eq!(line.text(), "");
+        line.process_key(Key::Ctrl('y'));
+        line.process_key(Key::Ctrl('y'));
+        assert_eq!(line.text(), "one twoone two");
+        line.process_key(Key::CtrlLeft);
+        line.process_key(Key::Ctrl('u'));
+        assert_eq!(line.text(), "two");
+        assert_eq!(line.killed, "one twoone ");
+    }
+
+    #[test]
+    fn scrolls_to_cursor() {
+        let mut line = line("abcdefghij");
+        let mut scroll = 0;
+        assert_eq!(line.visible(&mut scroll, 5), "ghij");
+        assert_eq!(scroll, 6);
+        assert_eq!(line.cursor_column(scroll), 4);
+        line.process_key(Key::Home);
+        assert_eq!(line.visible(&mut scroll, 5), "abcde");
+        assert_eq!(scroll, 0);
+        let mut scroll = 0;
+        assert_eq!(
+            self::line("\u{4e2d}\u{6587}").visible(&mut scroll, 4),
+            "\u{6587}"
+        );
+    }
+
+    #[test]
+    fn completes_paths() {
+        let directory = env::temp_dir().join(format!("hecto-complete-{}", process::id()));
+        fs::create_dir_all(directory.join("source")).unwrap();
+        fs::write(directory.join("settings.txt"), "").unwrap();
+        fs::write(directory.join(".hidden"), "").unwrap();
+        let prefix = format!("{}{}", directory.to_string_lossy(), MAIN_SEPARATOR);
+        let (completed, names) = complete_path(&format!("{}so", prefix));
+        assert_eq!(completed, format!("{}source{}", prefix, MAIN_SEPARATOR));
+        assert_eq!(names, [format!("source{}", MAIN_SEPARATOR)]);
+        let (completed, names) = complete_path(&format!("{}s", prefix));
+        assert_eq!(completed, format!("{}s", prefix));
+        assert_eq!(names.len(), 2);
+        let (_, names) = complete_path(&prefix);
+        assert_eq!(names.len(), 2);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
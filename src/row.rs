@@ -1,10 +1,13 @@
 use crate::highlighting;
 use crate::HighlightingOptions;
 use crate::SearchDirection;
+use crate::SearchOptions;
 
-use std::cmp;
 use termion::color;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+pub const SELECTION_BG_COLOR: color::Rgb = color::Rgb(73, 80, 120);
 
 #[derive(Default)]
 pub struct Row {
@@ -26,42 +29,201 @@ impl From<&str> for Row {
 }
 
 impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
+    /// Render part of row between screen columns `start` and `end`.
+    /// Graphemes with index in `selection` range are drawn with selection background.
+    pub fn render(
+        &self,
+        start: usize,
+        end: usize,
+        selection: Option<(usize, usize)>,
+        tab_width: usize,
+    ) -> String {
+        self.render_with_background(start, end, tab_width, None, |index| {
+            selection
+                .filter(|&(from, to)| from <= index && index < to)
+                .map(|_| SELECTION_BG_COLOR)
+        })
+    }
+
+    /// Render screen columns from `start` to `end`, `background` gives color behind grapheme at an index.
+    /// Highlighting of the whole row is replaced by `overlay` e.g. in merge conflict, search matches are kept.
+    pub fn render_with_background<F>(
+        &self,
+        start: usize,
+        end: usize,
+        tab_width: usize,
+        overlay: Option<highlighting::Type>,
+        background: F,
+    ) -> String
+    where
+        F: Fn(usize) -> Option<color::Rgb>,
+    {
         let mut result = String::new();
         let mut current_highlighting = &highlighting::Type::None;
-        #[allow(clippy::integer_arithmetic)]
-        for (index, grapheme) in self
-            .string
-            .graphemes(true)
-            .enumerate()
-            .skip(start)
-            .take(end - start)
-        {
-            if let Some(c) = grapheme.chars().next() {
-                let highlighting_type = self
-                    .highlighting
-                    .get(index)
-                    .unwrap_or(&highlighting::Type::None);
-                if highlighting_type != current_highlighting {
-                    current_highlighting = highlighting_type;
-                    let start_highlight =
-                        format!("{}", termion::color::Fg(highlighting_type.to_color()));
-                    result.push_str(&start_highlight);
+        let mut current_background = None;
+        let mut column: usize = 0;
+        for (index, grapheme) in self.string.graphemes(true).enumerate() {
+            let next_column = column.saturating_add(grapheme_width(grapheme, column, tab_width));
+            if next_column <= start {
+                column = next_column;
+                continue;
+            }
+            if column >= end {
+                break;
+            }
+            let mut highlighting_type = self
+                .highlighting
+                .get(index)
+                .unwrap_or(&highlighting::Type::None);
+            if let Some(ref overlay) = overlay {
+                if *highlighting_type != highlighting::Type::Match {
+                    highlighting_type = overlay;
                 }
-                if c == '\t' {
-                    result.push(' ');
+            }
+            if highlighting_type != current_highlighting {
+                current_highlighting = highlighting_type;
+                let start_highlight =
+                    format!("{}", termion::color::Fg(highlighting_type.to_color()));
+                result.push_str(&start_highlight);
+            }
+            let bg_color = background(index);
+            if bg_color != current_background {
+                current_background = bg_color;
+                if let Some(bg_color) = bg_color {
+                    result.push_str(&format!("{}", color::Bg(bg_color)));
                 } else {
-                    result.push(c);
+                    result.push_str(&format!("{}", color::Bg(color::Reset)));
                 }
             }
+            if column < start || next_column > end || grapheme == "\t" {
+                // Tab or wide grapheme cut by the edge of screen
+                let visible = next_column.min(end).saturating_sub(column.max(start));
+                result.push_str(&" ".repeat(visible));
+            } else {
+                result.push_str(grapheme);
+            }
+            column = next_column;
+        }
+        if current_background.is_some() {
+            result.push_str(&format!("{}", color::Bg(color::Reset)));
         }
         let end_highlight = format!("{}", termion::color::Fg(color::Reset));
         result.push_str(&end_highlight);
         result
     }
 
+    /// Screen column where grapheme at index `x` starts
+    pub fn width_until(&self, x: usize, tab_width: usize) -> usize {
+        self.string
+            .graphemes(true)
+            .take(x)
+            .fold(0, |column, grapheme| {
+                column.saturating_add(grapheme_width(grapheme, column, tab_width))
+            })
+    }
+
+    /// Index of grapheme drawn at screen `column`. Columns after the end of row map to its length.
+    pub fn x_at_column(&self, column: usize, tab_width: usize) -> usize {
+        let mut width: usize = 0;
+        for (index, grapheme) in self.string.graphemes(true).enumerate() {
+            width = width.saturating_add(grapheme_width(grapheme, width, tab_width));
+            if width > column {
+                return index;
+            }
+        }
+        self.len
+    }
+
+    /// Range of graphemes drawn at least partly in screen columns from `start` to `end`
+    pub fn range_in_columns(&self, start: usize, end: usize, tab_width: usize) -> (usize, usize) {
+        let from = self.x_at_column(start, tab_width);
+        if end <= start {
+            return (from, from);
+        }
+        let to = self
+            .x_at_column(end.saturating_sub(1), tab_width)
+            .saturating_add(1)
+            .min(self.len);
+        (from, to.max(from))
+    }
+
+    /// Text before, inside and after screen columns from `start` to `end`. Tabs inside the columns,
+    /// and tabs or wide graphemes cut by their edges, are turned into spaces of the same width.
+    pub fn split_columns(
+        &self,
+        start: usize,
+        end: usize,
+        tab_width: usize,
+    ) -> (String, String, String) {
+        let mut before = String::new();
+        let mut inside = String::new();
+        let mut after = String::new();
+        let mut column: usize = 0;
+        for grapheme in self.string.graphemes(true) {
+            let next_column = column.saturating_add(grapheme_width(grapheme, column, tab_width));
+            if next_column <= start {
+                before.push_str(grapheme);
+            } else if column >= end {
+                after.push_str(grapheme);
+            } else if column >= start && next_column <= end && grapheme != "\t" {
+                inside.push_str(grapheme);
+            } else {
+                before.push_str(&" ".repeat(start.saturating_sub(column)));
+                inside
+                    .push_str(&" ".repeat(next_column.min(end).saturating_sub(column.max(start))));
+                after.push_str(&" ".repeat(next_column.saturating_sub(end.max(column))));
+            }
+            column = next_column;
+        }
+        (before, inside, after)
+    }
+
+    /// Grapheme range of word (or run of whitespace/punctuation) at index `x`,
+    /// according to Unicode word boundaries
+    pub fn word_at(&self, x: usize) -> (usize, usize) {
+        let mut start: usize = 0;
+        for word in self.string.split_word_bounds() {
+            let end = start.saturating_add(word.graphemes(true).count());
+            if x < end {
+                return (start, end);
+            }
+            start = end;
+        }
+        (self.len, self.len)
+    }
+
+    /// Grapheme ranges of words according to Unicode word boundaries.
+    /// Runs of whitespace and punctuation are not words.
+    fn words(&self) -> Vec<(usize, usize)> {
+        let mut words = Vec::new();
+        let mut start: usize = 0;
+        for word in self.string.split_word_bounds() {
+            let end = start.saturating_add(word.graphemes(true).count());
+            if word.chars().any(char::is_alphanumeric) {
+                words.push((start, end));
+            }
+            start = end;
+        }
+        words
+    }
+
+    /// Start of the word before index `x`
+    pub fn previous_word_start(&self, x: usize) -> Option<usize> {
+        self.words()
+            .into_iter()
+            .rev()
+            .map(|(start, _)| start)
+            .find(|&start| start < x)
+    }
+
+    /// End of the word after index `x`
+    pub fn next_word_end(&self, x: usize) -> Option<usize> {
+        self.words()
+            .into_iter()
+            .map(|(_, end)| end)
+            .find(|&end| end > x)
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
@@ -70,6 +232,11 @@ impl Row {
         self.len == 0
     }
 
+    /// Whether the row contains only whitespace
+    pub fn is_blank(&self) -> bool {
+        self.string.trim().is_empty()
+    }
+
     #[allow(clippy::integer_arithmetic)]
     pub fn insert(&mut self, at: usize, c: char) {
         if at >= self.len() {
@@ -146,42 +313,56 @@ impl Row {
         self.string.as_bytes()
     }
 
-    pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
+    /// Index of the first match of `query` at or after `at` when searching forward,
+    /// or the last match starting before `at` when searching backward
+    pub fn find(
+        &self,
+        query: &str,
+        at: usize,
+        direction: SearchDirection,
+        options: SearchOptions,
+    ) -> Option<usize> {
+        let ignore_case = options.ignores_case(query);
+        let query: Vec<&str> = query.graphemes(true).collect();
         if query.is_empty() {
             return None;
         }
-
-        let start = if direction == SearchDirection::Forward {
-            at
+        let graphemes: Vec<&str> = self.string.graphemes(true).collect();
+        let boundaries = if options.whole_word {
+            self.word_boundaries()
         } else {
-            0
+            Vec::new()
         };
-        let end = if direction == SearchDirection::Forward {
-            self.len
-        } else {
-            at
+        let is_match = |start: &usize| {
+            let end = start.saturating_add(query.len());
+            graphemes.get(*start..end).is_some_and(|candidate| {
+                candidate.iter().zip(&query).all(|(grapheme, query)| {
+                    grapheme == query
+                        || ignore_case && grapheme.to_lowercase() == query.to_lowercase()
+                })
+            }) && (!options.whole_word
+                || boundaries.binary_search(start).is_ok()
+                    && boundaries.binary_search(&end).is_ok())
         };
-        #[allow(clippy::integer_arithmetic)]
-        let substring: String = self
-            .string
-            .graphemes(true)
-            .skip(start)
-            .take(end - start)
-            .collect();
-        let matching_byte_index = if direction == SearchDirection::Forward {
-            substring.find(query)
+        let last_start = graphemes.len().checked_sub(query.len())?;
+        if direction == SearchDirection::Forward {
+            (at..=last_start).find(is_match)
         } else {
-            substring.rfind(query)
-        };
-        if let Some(matching_byte_index_unwrapped) = matching_byte_index {
-            for (grapheme_index, (byte_index, _)) in substring.grapheme_indices(true).enumerate() {
-                if matching_byte_index_unwrapped == byte_index {
-                    #[allow(clippy::integer_arithmetic)]
-                    return Some(start + grapheme_index);
-                }
-            }
+            (0..at.min(last_start.saturating_add(1)))
+                .rev()
+                .find(is_match)
         }
-        None
+    }
+
+    /// Grapheme indices of Unicode word boundaries, including start and end of row
+    fn word_boundaries(&self) -> Vec<usize> {
+        let mut boundaries = vec![0];
+        let mut index: usize = 0;
+        for word in self.string.split_word_bounds() {
+            index = index.saturating_add(word.graphemes(true).count());
+            boundaries.push(index);
+        }
+        boundaries
     }
 
     #[allow(clippy::integer_arithmetic)]
@@ -189,6 +370,7 @@ impl Row {
         &mut self,
         opts: &HighlightingOptions,
         word: &Option<String>,
+        search_options: SearchOptions,
         mut start_with_comment: bool,
     ) -> bool {
         let row = self.string.clone();
@@ -215,6 +397,12 @@ impl Row {
         }
 
         self.highlighting = Vec::new();
+        if opts.diff() {
+            self.highlight_diff_line(&graphemes);
+            self.highlight_match(word, search_options);
+            self.is_highlighted = true;
+            return false;
+        }
         let mut index = 0;
 
         #[allow(clippy::shadow_unrelated)]
@@ -244,20 +432,36 @@ impl Row {
             index = index.saturating_add(1);
         }
 
-        self.highlight_match(word);
+        self.highlight_match(word, search_options);
         self.is_highlighted = true;
         start_with_comment
     }
 
+    /// Highlight whole line of unified diff according to its prefix
+    fn highlight_diff_line(&mut self, graphemes: &[&str]) {
+        let hl_type = if self.string.starts_with("+++") || self.string.starts_with("---") {
+            highlighting::Type::DiffHeader
+        } else if self.string.starts_with("@@") {
+            highlighting::Type::DiffHunk
+        } else if self.string.starts_with('+') {
+            highlighting::Type::DiffAdded
+        } else if self.string.starts_with('-') {
+            highlighting::Type::DiffDeleted
+        } else {
+            highlighting::Type::None
+        };
+        self.highlighting = vec![hl_type; graphemes.len()];
+    }
+
     /// Find and highlight all search matches on current line
-    fn highlight_match(&mut self, word: &Option<String>) {
+    fn highlight_match(&mut self, word: &Option<String>, options: SearchOptions) {
         if let Some(ref word_unwrapped) = *word {
             if word_unwrapped.is_empty() {
                 return;
             }
             let mut index = 0;
             while let Some(search_match) =
-                self.find(word_unwrapped, index, SearchDirection::Forward)
+                self.find(word_unwrapped, index, SearchDirection::Forward, options)
             {
                 if let Some(next_index) =
                     search_match.checked_add(word_unwrapped.graphemes(true).count())
@@ -535,8 +739,141 @@ impl Row {
     }
 }
 
+/// Number of screen columns taken by grapheme drawn at `column`. Tab reaches the next multiple of `tab_width`.
+fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_width = tab_width.max(1);
+        tab_width.saturating_sub(column.checked_rem(tab_width).unwrap_or(0))
+    } else {
+        grapheme.width().max(1)
+    }
+}
+
 fn is_separator(grapheme: &str) -> bool {
     grapheme
         .chars()
         .any(|c| c.is_ascii_punctuation() || c.is_ascii_whitespace())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileType;
+
+    fn split(text: &str, start: usize, end: usize) -> (String, String, String) {
+        Row::from(text).split_columns(start, end, 4)
+    }
+
+    fn strings(before: &str, inside: &str, after: &str) -> (String, String, String) {
+        (before.to_owned(), inside.to_owned(), after.to_owned())
+    }
+
+    #[test]
+    fn highlights_diff_lines_by_prefix() {
+        let opts = FileType::diff();
+        let highlighted = |text: &str| {
+            let mut row = Row::from(text);
+            row.highlight(
+                opts.highlighting_options(),
+                &None,
+                SearchOptions::default(),
+                false,
+            );
+            row.highlighting
+        };
+        assert!(highlighted("+++ b/file") == [highlighting::Type::DiffHeader; 10]);
+        assert!(highlighted("--- a/file") == [highlighting::Type::DiffHeader; 10]);
+        assert!(highlighted("@@ -1 +1 @@") == [highlighting::Type::DiffHunk; 11]);
+        assert!(highlighted("+fn x() {}") == [highlighting::Type::DiffAdded; 10]);
+        assert!(highlighted("-\"a\" 1") == [highlighting::Type::DiffDeleted; 6]);
+        // Context lines aren't highlighted as code
+        assert!(highlighted(" fn 1") == [highlighting::Type::None; 5]);
+    }
+
+    #[test]
+    fn splits_plain_text() {
+        assert_eq!(split("abcdef", 2, 4), strings("ab", "cd", "ef"));
+        assert_eq!(split("abcdef", 0, 6), strings("", "abcdef", ""));
+        assert_eq!(split("abc", 4, 6), strings("abc", "", ""));
+    }
+
+    #[test]
+    fn turns_tabs_into_spaces() {
+        assert_eq!(split("\tx", 2, 5), strings("  ", "  x", ""));
+        assert_eq!(split("a\tb", 0, 8), strings("", "a   b", ""));
+        assert_eq!(split("\tx\ty", 1, 2), strings(" ", " ", "  x\ty"));
+    }
+
+    #[test]
+    fn cuts_wide_graphemes() {
+        assert_eq!(split("a\u{4e2d}b", 2, 4), strings("a ", " b", ""));
+        assert_eq!(split("a\u{4e2d}b", 1, 3), strings("a", "\u{4e2d}", "b"));
+    }
+
+    #[test]
+    fn maps_graphemes_to_columns() {
+        let row = Row::from("a\u{4e2d}\tb");
+        assert_eq!(row.width_until(1, 4), 1);
+        assert_eq!(row.width_until(2, 4), 3);
+        assert_eq!(row.width_until(3, 4), 4);
+        assert_eq!(row.width_until(4, 4), 5);
+        assert_eq!(row.x_at_column(0, 4), 0);
+        assert_eq!(row.x_at_column(1, 4), 1);
+        assert_eq!(row.x_at_column(2, 4), 1);
+        assert_eq!(row.x_at_column(3, 4), 2);
+        assert_eq!(row.x_at_column(4, 4), 3);
+        assert_eq!(row.x_at_column(20, 4), 4);
+    }
+
+    #[test]
+    fn finds_word_at_index() {
+        let row = Row::from("let answer = 42;");
+        assert_eq!(row.word_at(0), (0, 3));
+        assert_eq!(row.word_at(6), (4, 10));
+        assert_eq!(row.word_at(10), (10, 11));
+        assert_eq!(row.word_at(13), (13, 15));
+        assert_eq!(row.word_at(30), (16, 16));
+    }
+
+    fn options(ignore_case: bool, smart_case: bool, whole_word: bool) -> SearchOptions {
+        SearchOptions {
+            ignore_case,
+            smart_case,
+            whole_word,
+            wrap_around: false,
+        }
+    }
+
+    #[test]
+    fn finds_with_search_options() {
+        let row = Row::from("Word words word");
+        let find = |query, options| row.find(query, 0, SearchDirection::Forward, options);
+        assert_eq!(find("word", options(false, false, false)), Some(5));
+        assert_eq!(find("word", options(true, false, false)), Some(0));
+        assert_eq!(find("word", options(false, true, false)), Some(0));
+        assert_eq!(find("Word", options(true, true, false)), Some(0));
+        assert_eq!(find("wORD", options(false, true, false)), None);
+        assert_eq!(find("word", options(false, false, true)), Some(11));
+        assert_eq!(find("wor", options(true, false, true)), None);
+    }
+
+    #[test]
+    fn finds_in_both_directions() {
+        let row = Row::from("abab");
+        let options = options(false, false, false);
+        assert_eq!(
+            row.find("ab", 1, SearchDirection::Forward, options),
+            Some(2)
+        );
+        assert_eq!(row.find("ab", 3, SearchDirection::Forward, options), None);
+        assert_eq!(
+            row.find("ab", 2, SearchDirection::Backward, options),
+            Some(0)
+        );
+        assert_eq!(
+            row.find("ab", 4, SearchDirection::Backward, options),
+            Some(2)
+        );
+        assert_eq!(row.find("", 0, SearchDirection::Forward, options), None);
+    }
+}
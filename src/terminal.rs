@@ -1,20 +1,65 @@
 use crate::Position;
 
+use std::collections::VecDeque;
 use std::io::{self, stdout, Write};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::time::Duration;
 use termion::{
     color,
-    event::Key,
-    input::TermRead,
+    event::{self, Event, Key},
+    input::MouseTerminal,
     raw::{IntoRawMode, RawTerminal},
 };
 
+/// Set by SIGWINCH handler when terminal window was resized
+static RESIZED: AtomicBool = AtomicBool::new(false);
+/// Write end of the pipe which wakes up waiting for input when a signal arrives
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+/// How long to wait for the rest of an escape sequence that didn't arrive in one read
+const ESCAPE_SEQUENCE_TIMEOUT: Duration = Duration::from_millis(20);
+/// Ctrl-Delete and Ctrl-Backspace, which termion has no keys for. They are reported as Ctrl with the
+/// control character of the key, which termion never produces.
+pub const CTRL_DELETE: Key = Key::Ctrl('\x7f');
+pub const CTRL_BACKSPACE: Key = Key::Ctrl('\x08');
+const CTRL_DELETE_SEQUENCE: &[u8] = b"\x1b[3;5~";
+/// Ctrl-Backspace in xterm's modifyOtherKeys mode and in kitty's keyboard protocol
+const CTRL_BACKSPACE_SEQUENCES: [&[u8]; 2] = [b"\x1b[27;5;127~", b"\x1b[127;5u"];
+/// DEL, which Backspace sends by default
+const DEL: u8 = 0x7f;
+/// BS, which Backspace sends in some terminals
+const BS: u8 = 0x08;
+/// Markers around pasted text sent by terminal in bracketed paste mode
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+extern "C" fn handle_sigwinch(_: libc::c_int) {
+    RESIZED.store(true, Ordering::SeqCst);
+    let fd = SIGNAL_PIPE.load(Ordering::SeqCst);
+    if fd >= 0_i32 {
+        let byte = 1_u8;
+        // SAFETY: write(2) is async-signal-safe and the buffer lives until the call returns
+        #[allow(unsafe_code)]
+        unsafe {
+            libc::write(fd, (&byte as *const u8).cast::<libc::c_void>(), 1);
+        }
+    }
+}
+
 pub struct Size {
     pub width: u16,
     pub height: u16,
 }
 pub struct Terminal {
     size: Size,
-    _stdout: RawTerminal<std::io::Stdout>,
+    _stdout: MouseTerminal<RawTerminal<std::io::Stdout>>,
+    /// Bytes read from stdin which weren't parsed to events yet
+    input: VecDeque<u8>,
+    /// Read end of the pipe written by signal handlers
+    signal_pipe: libc::c_int,
+    /// Events are parsed from pasted text, which is inserted as it is
+    pasting: bool,
+    /// Byte sent by Backspace according to terminal settings, the other one of DEL and BS is Ctrl-Backspace
+    erase: u8,
 }
 
 impl Terminal {
@@ -23,19 +68,49 @@ impl Terminal {
     /// Will return `Err` if terminal fails to initialize
     pub fn default() -> io::Result<Self> {
         let size = termion::terminal_size()?;
+        let signal_pipe = watch_resize()?;
+        let erase = erase_byte();
+        print!("\x1b[?2004h");
         Ok(Self {
             size: Size {
                 width: size.0,
                 height: size.1.saturating_sub(2),
             },
-            _stdout: stdout().into_raw_mode()?,
+            _stdout: MouseTerminal::from(stdout().into_raw_mode()?),
+            input: VecDeque::new(),
+            signal_pipe,
+            pasting: false,
+            erase,
         })
     }
 
+    /// Read terminal size again if the window was resized since the last call.
+    /// Returns `true` when size was updated.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if terminal size cannot be read
+    pub fn update_size(&mut self) -> io::Result<bool> {
+        if !RESIZED.swap(false, Ordering::SeqCst) {
+            return Ok(false);
+        }
+        let size = termion::terminal_size()?;
+        self.size = Size {
+            width: size.0,
+            height: size.1.saturating_sub(2),
+        };
+        Ok(true)
+    }
+
     pub fn size(&self) -> &Size {
         &self.size
     }
 
+    /// Whether the last key was pasted rather than typed
+    pub fn is_pasting(&self) -> bool {
+        self.pasting
+    }
+
     pub fn clear_screen() {
         print!("{}", termion::clear::All);
     }
@@ -55,17 +130,159 @@ impl Terminal {
         io::stdout().flush()
     }
 
+    /// Wait for keyboard or mouse event at most `timeout`, or forever if it's `None`.
+    /// Returns `None` when timeout elapses or a signal (e.g. terminal resize) arrives.
+    ///
     /// # Errors
     ///
-    /// An error occurs when key reading fails.
-    pub fn read_key() -> io::Result<Key> {
-        loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
+    /// An error occurs when waiting for stdin or reading of it fails.
+    pub fn read_event(&mut self, timeout: Option<Duration>) -> io::Result<Option<Event>> {
+        if self.input.is_empty() {
+            let (stdin_ready, signal_ready) = self.poll(timeout)?;
+            if signal_ready {
+                self.drain_signal_pipe();
+            }
+            if !stdin_ready {
+                return Ok(None);
+            }
+            self.read_input()?;
+        }
+        if self.escape_sequence_incomplete() && self.poll(Some(ESCAPE_SEQUENCE_TIMEOUT))?.0 {
+            self.read_input()?;
+        }
+        Ok(self.parse_input())
+    }
+
+    /// Wait until stdin or signal pipe is readable. Returns readiness of both.
+    fn poll(&self, timeout: Option<Duration>) -> io::Result<(bool, bool)> {
+        let mut fds = [
+            libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: self.signal_pipe,
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        let timeout = timeout.map_or(-1_i32, |timeout| {
+            libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX)
+        });
+        // SAFETY: `fds` is a valid array of two `pollfd` structures
+        #[allow(unsafe_code)]
+        let result = unsafe { libc::poll(fds.as_mut_ptr(), 2, timeout) };
+        if result < 0_i32 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                return Ok((false, true));
+            }
+            return Err(error);
+        }
+        let [stdin, signal] = fds;
+        Ok((
+            stdin.revents & (libc::POLLIN | libc::POLLHUP) != 0,
+            signal.revents & libc::POLLIN != 0,
+        ))
+    }
+
+    /// Read all bytes available on stdin
+    fn read_input(&mut self) -> io::Result<()> {
+        let mut buffer = [0_u8; 4096];
+        // SAFETY: `buffer` is valid for writes of its whole length
+        #[allow(unsafe_code)]
+        let read = unsafe {
+            libc::read(
+                libc::STDIN_FILENO,
+                buffer.as_mut_ptr().cast::<libc::c_void>(),
+                buffer.len(),
+            )
+        };
+        match usize::try_from(read) {
+            Ok(0) => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(read) => {
+                self.input.extend(buffer.iter().take(read));
+                Ok(())
+            }
+            Err(_) => {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    Ok(())
+                } else {
+                    Err(error)
+                }
             }
         }
     }
 
+    fn drain_signal_pipe(&self) {
+        let mut buffer = [0_u8; 64];
+        // SAFETY: `buffer` is valid for writes of its whole length, the pipe is non-blocking
+        #[allow(unsafe_code)]
+        unsafe {
+            while libc::read(
+                self.signal_pipe,
+                buffer.as_mut_ptr().cast::<libc::c_void>(),
+                buffer.len(),
+            ) > 0
+            {}
+        }
+    }
+
+    /// Whether the input starts with CSI sequence (`ESC [`) whose final byte hasn't been read yet
+    fn escape_sequence_incomplete(&self) -> bool {
+        let mut bytes = self.input.iter();
+        if bytes.next() != Some(&0x1b) || bytes.next() != Some(&b'[') {
+            return false;
+        }
+        match bytes.next() {
+            // X10 mouse encoding has three bytes after `ESC [ M`
+            Some(&b'M') => bytes.count() < 3,
+            Some(&byte) if (0x40..=0x7e).contains(&byte) => false,
+            Some(_) => !bytes.any(|byte| (0x40..=0x7e).contains(byte)),
+            None => true,
+        }
+    }
+
+    /// Parse one event from the beginning of input
+    fn parse_input(&mut self) -> Option<Event> {
+        let first = self.input.pop_front()?;
+        // Lone ESC is a key press, an escape sequence comes in one read with its leading ESC
+        if first == 0x1b && self.input.is_empty() {
+            return Some(Event::Key(Key::Esc));
+        }
+        if first == DEL || first == BS {
+            let key = if first == self.erase {
+                Key::Backspace
+            } else {
+                CTRL_BACKSPACE
+            };
+            return Some(Event::Key(key));
+        }
+        let mut consumed = 0_usize;
+        let result = {
+            let mut bytes = self.input.iter().map(|byte| {
+                consumed = consumed.saturating_add(1);
+                Ok(*byte)
+            });
+            event::parse_event(first, &mut bytes)
+        };
+        let mut raw = vec![first];
+        raw.extend(self.input.drain(..consumed));
+        if raw == CTRL_DELETE_SEQUENCE {
+            return Some(Event::Key(CTRL_DELETE));
+        }
+        if CTRL_BACKSPACE_SEQUENCES.contains(&raw.as_slice()) {
+            return Some(Event::Key(CTRL_BACKSPACE));
+        }
+        if raw == PASTE_START || raw == PASTE_END {
+            self.pasting = raw == PASTE_START;
+            return Some(Event::Unsupported(raw));
+        }
+        Some(result.unwrap_or(Event::Unsupported(raw)))
+    }
+
     pub fn cursor_hide() {
         print!("{}", termion::cursor::Hide);
     }
@@ -94,3 +311,75 @@ impl Terminal {
         print!("{}", color::Fg(color::Reset));
     }
 }
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?2004l");
+        Self::flush().ok();
+    }
+}
+
+/// Erase character of terminal, i.e. byte sent by Backspace. DEL if terminal settings can't be read.
+fn erase_byte() -> u8 {
+    // SAFETY: `termios` is a valid structure for tcgetattr to fill
+    #[allow(unsafe_code)]
+    let termios = unsafe {
+        let mut termios: libc::termios = std::mem::zeroed();
+        (libc::tcgetattr(libc::STDIN_FILENO, &mut termios) == 0_i32).then_some(termios)
+    };
+    termios
+        .and_then(|termios| termios.c_cc.get(libc::VERASE).copied())
+        .filter(|&erase| erase == BS)
+        .unwrap_or(DEL)
+}
+
+/// Install SIGWINCH handler, which wakes up waiting for input through a pipe.
+/// Returns read end of the pipe.
+fn watch_resize() -> io::Result<libc::c_int> {
+    let mut fds = [0_i32; 2];
+    // SAFETY: `fds` is a valid array of two file descriptors, the handler uses only async-signal-safe calls
+    #[allow(unsafe_code, clippy::fn_to_numeric_cast_any)]
+    let result = unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0_i32 {
+            return Err(io::Error::last_os_error());
+        }
+        for fd in fds {
+            libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        let [_, write_end] = fds;
+        SIGNAL_PIPE.store(write_end, Ordering::SeqCst);
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_sigwinch as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGWINCH, &action, std::ptr::null_mut())
+    };
+    if result == 0 {
+        let [read_end, _] = fds;
+        Ok(read_end)
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_signal_wakes_up_waiting() {
+        let read_end = watch_resize().unwrap();
+        RESIZED.store(false, Ordering::SeqCst);
+        let mut byte = 0_u8;
+        // SAFETY: the handler installed above uses only async-signal-safe calls, `byte` is valid for
+        // writes of one byte
+        #[allow(unsafe_code)]
+        let read = unsafe {
+            libc::raise(libc::SIGWINCH);
+            libc::read(read_end, (&mut byte as *mut u8).cast::<libc::c_void>(), 1)
+        };
+        assert!(RESIZED.load(Ordering::SeqCst));
+        assert_eq!(read, 1);
+    }
+}
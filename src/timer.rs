@@ -0,0 +1,114 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Task run periodically by the event loop with mutable access to its owner, e.g. `Editor`
+pub type Task<T> = fn(&mut T) -> io::Result<()>;
+
+/// Identifier of registered task, used to cancel it
+#[derive(PartialEq, Clone, Copy)]
+pub struct TimerId(usize);
+
+struct Timer<T> {
+    id: TimerId,
+    interval: Duration,
+    next_run: Instant,
+    task: Task<T>,
+}
+
+/// Periodic tasks waiting to be run by the event loop
+pub struct Timers<T> {
+    timers: Vec<Timer<T>>,
+    next_id: usize,
+}
+
+impl<T> Default for Timers<T> {
+    fn default() -> Self {
+        Self {
+            timers: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> Timers<T> {
+    /// Run `task` every `interval`, the first run is after `interval` elapses
+    pub fn register(&mut self, interval: Duration, task: Task<T>) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id = self.next_id.saturating_add(1);
+        self.timers.push(Timer {
+            id,
+            interval,
+            next_run: Instant::now()
+                .checked_add(interval)
+                .unwrap_or_else(Instant::now),
+            task,
+        });
+        id
+    }
+
+    /// Stop running task registered as `id`
+    pub fn cancel(&mut self, id: TimerId) {
+        self.timers.retain(|timer| timer.id != id);
+    }
+
+    /// Time when the earliest task is due
+    pub fn next_run(&self) -> Option<Instant> {
+        self.timers.iter().map(|timer| timer.next_run).min()
+    }
+
+    /// Tasks which are due at `now`. They are rescheduled for their next run.
+    pub fn due(&mut self, now: Instant) -> Vec<Task<T>> {
+        let mut tasks = Vec::new();
+        for timer in &mut self.timers {
+            if timer.next_run <= now {
+                tasks.push(timer.task);
+                timer.next_run = now.checked_add(timer.interval).unwrap_or(now);
+            }
+        }
+        tasks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(counter: &mut usize) -> io::Result<()> {
+        *counter = counter.saturating_add(1);
+        Ok(())
+    }
+
+    #[test]
+    fn runs_due_tasks() {
+        let mut timers: Timers<usize> = Timers::default();
+        let start = Instant::now();
+        timers.register(Duration::from_secs(1), count);
+        timers.register(Duration::from_secs(5), count);
+        let next_run = timers.next_run().unwrap();
+        assert!(next_run >= start + Duration::from_secs(1));
+        assert!(next_run < start + Duration::from_secs(5));
+
+        assert!(timers.due(start).is_empty());
+        let mut counter = 0;
+        let now = start + Duration::from_secs(2);
+        for task in timers.due(now) {
+            task(&mut counter).unwrap();
+        }
+        assert_eq!(counter, 1);
+        // Task which ran is scheduled again after its interval
+        assert!(timers.due(now).is_empty());
+        assert_eq!(timers.due(now + Duration::from_secs(4)).len(), 2);
+    }
+
+    #[test]
+    fn cancels_tasks() {
+        let mut timers: Timers<usize> = Timers::default();
+        let id = timers.register(Duration::from_millis(10), count);
+        timers.register(Duration::from_secs(10), count);
+        timers.cancel(id);
+        let later = Instant::now() + Duration::from_secs(1);
+        assert!(timers.due(later).is_empty());
+        assert_eq!(timers.due(later + Duration::from_secs(10)).len(), 1);
+        assert!(timers.next_run().is_some());
+    }
+}